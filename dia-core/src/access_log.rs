@@ -0,0 +1,557 @@
+//! Access log module for dia framework
+//!
+//! Provides the AccessLog middleware, writing one line per request in Common
+//! or Combined Log Format or as JSON, to the `log` crate, stdout or a
+//! size-rotated file.
+
+use crate::cookie::time::format_description::well_known::Rfc3339;
+use crate::cookie::time::OffsetDateTime;
+use crate::middleware::Middleware;
+use crate::{Request, Response};
+use serde_json::json;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Default size at which the log file is rotated (10 MiB)
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Default number of rotated files kept next to the active one
+pub const DEFAULT_MAX_FILES: usize = 5;
+
+/// Header carrying the request id
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Access log line format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `host ident user [time] "request" status bytes`
+    #[default]
+    Common,
+    /// Common Log Format followed by `"referer" "user-agent"`
+    Combined,
+    /// One JSON object per request
+    Json,
+}
+
+/// Id of the current request, available from `Request::extensions`
+///
+/// Taken from the `X-Request-Id` request header when present, generated
+/// otherwise, and echoed in the response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Generate an id unique within this process
+    fn generate() -> Self {
+        static START: OnceLock<u64> = OnceLock::new();
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        let start = START.get_or_init(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        Self(format!("{:x}-{:x}", start, NEXT.fetch_add(1, Ordering::Relaxed)))
+    }
+}
+
+/// Query parameters whose values the access log replaces with `REDACTED`
+///
+/// Middlewares that read secrets from the query string, like
+/// `ApiKeyMiddleware` with `ApiKeySource::Query`, mark them with
+/// `SensitiveQuery::mark`.
+#[derive(Debug, Clone, Default)]
+pub struct SensitiveQuery(Vec<String>);
+
+impl SensitiveQuery {
+    /// Mark a query parameter of the request as sensitive
+    pub fn mark(req: &mut Request, name: &str) {
+        let mut sensitive = req.extensions().get::<SensitiveQuery>().cloned().unwrap_or_default();
+        if !sensitive.0.iter().any(|marked| marked == name) {
+            sensitive.0.push(name.to_string());
+            req.extensions_mut().insert(sensitive);
+        }
+    }
+
+    /// Rewrite a query string with the marked values redacted
+    fn redact(&self, query: &str) -> String {
+        query
+            .split('&')
+            .map(|pair| {
+                let key = pair.split_once('=').map_or(pair, |(key, _)| key);
+                let sensitive = form_urlencoded::parse(key.as_bytes())
+                    .next()
+                    .is_some_and(|(name, _)| self.0.iter().any(|marked| *marked == name));
+                if sensitive {
+                    format!("{}=REDACTED", key)
+                } else {
+                    pair.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+/// Where access log lines are written
+#[derive(Debug, Clone)]
+enum Output {
+    /// `log::info!` with the `dia::access` target
+    Log,
+    /// Standard output
+    Stdout,
+    /// A file rotated by size
+    File(PathBuf),
+}
+
+/// Lines waiting for the writer thread before new ones are dropped
+const QUEUE_SIZE: usize = 8192;
+
+/// Log file renamed to `<path>.1`, `<path>.2`, ... once it grows too large
+#[derive(Debug)]
+struct RotatingFile {
+    /// Path of the active file
+    path: PathBuf,
+    /// Open handle, created lazily
+    file: Option<File>,
+    /// Current size of the active file
+    size: u64,
+    /// Size at which the file is rotated
+    max_size: u64,
+    /// Number of rotated files to keep
+    max_files: usize,
+}
+
+impl RotatingFile {
+    /// Start a thread appending the lines it receives to the file
+    ///
+    /// The thread exits once every sender has been dropped.
+    fn spawn(self) -> io::Result<SyncSender<String>> {
+        let (tx, rx) = mpsc::sync_channel::<String>(QUEUE_SIZE);
+        let mut file = self;
+        thread::Builder::new()
+            .name("dia-access-log".to_string())
+            .spawn(move || {
+                for line in rx {
+                    if let Err(err) = file.write_line(&line) {
+                        log::error!("Failed to write access log {}: {}", file.path.display(), err);
+                    }
+                }
+            })?;
+        Ok(tx)
+    }
+
+    /// Append a line, rotating first if it would exceed the size limit
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.file.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            self.size = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        if self.size > 0 && self.size + line.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+
+        let file = self.file.as_mut().expect("log file opened above");
+        writeln!(file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    /// Shift rotated files up by one and start a new active file
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let rotated = |n: usize| {
+                let mut path = self.path.clone().into_os_string();
+                path.push(format!(".{}", n));
+                PathBuf::from(path)
+            };
+            for n in (1..self.max_files).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+
+        self.file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Middleware writing an access log line for every request
+///
+/// Add it first so it wraps every other middleware and logs their
+/// short-circuit responses too. `Application` installs a default one
+/// (Common Log Format through the `log` crate) unless `Application::access_log`
+/// is used.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use dia_core::Application;
+/// use dia_core::access_log::{AccessLog, LogFormat};
+///
+/// let app = Application::new().access_log(
+///     AccessLog::new()
+///         .format(LogFormat::Json)
+///         .file("logs/access.log")
+///         .max_file_size(50 * 1024 * 1024)
+///         .max_files(10),
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    /// Line format
+    format: LogFormat,
+    /// Where lines are written
+    output: Output,
+    /// Size at which the log file is rotated
+    max_file_size: u64,
+    /// Number of rotated files to keep
+    max_files: usize,
+    /// Channel to the file writer thread, started on the first request and
+    /// shared between clones
+    writer: Arc<OnceLock<Option<SyncSender<String>>>>,
+}
+
+/// Time at which AccessLog saw the request
+struct RequestStart(Instant, OffsetDateTime);
+
+impl AccessLog {
+    /// Create an access log in Common Log Format written through the `log` crate
+    pub fn new() -> Self {
+        Self {
+            format: LogFormat::Common,
+            output: Output::Log,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            writer: Arc::default(),
+        }
+    }
+
+    /// Set the line format
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Write lines to standard output
+    pub fn stdout(mut self) -> Self {
+        self.output = Output::Stdout;
+        self
+    }
+
+    /// Write lines to a file, opened on the first request
+    ///
+    /// Lines are written and the file rotated on a dedicated thread, so
+    /// requests never wait for the disk; lines are dropped with a warning if
+    /// the disk falls too far behind.
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.output = Output::File(path.as_ref().to_path_buf());
+        self.writer = Arc::default();
+        self
+    }
+
+    /// Set the size in bytes at which the log file is rotated
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self.writer = Arc::default();
+        self
+    }
+
+    /// Set the number of rotated files to keep
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self.writer = Arc::default();
+        self
+    }
+
+    /// Format the log line for a finished request
+    fn line(&self, req: &Request, resp: &Response, started: OffsetDateTime, duration: Duration) -> String {
+        let request_id = req.extensions().get::<RequestId>().map(|id| id.0.as_str());
+        let target = match (req.query_string(), req.extensions().get::<SensitiveQuery>()) {
+            ("", _) => req.path().to_string(),
+            (query, Some(sensitive)) => format!("{}?{}", req.path(), sensitive.redact(query)),
+            (query, None) => format!("{}?{}", req.path(), query),
+        };
+
+        match self.format {
+            LogFormat::Common | LogFormat::Combined => {
+                let month = started.month().to_string();
+                let timestamp = format!(
+                    "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+                    started.day(),
+                    &month[..3],
+                    started.year(),
+                    started.hour(),
+                    started.minute(),
+                    started.second(),
+                );
+                let mut line = format!(
+                    "{} - - [{}] \"{} {} {}\" {} {}",
+                    req.remote_ip().map(String::as_str).unwrap_or("-"),
+                    timestamp,
+                    req.method(),
+                    target,
+                    req.version(),
+                    resp.get_status(),
+                    resp.get_body_len().map_or_else(|| "-".to_string(), |len| len.to_string()),
+                );
+                if self.format == LogFormat::Combined {
+                    line.push_str(&format!(
+                        " \"{}\" \"{}\"",
+                        escape(req.header("referer").unwrap_or("-")),
+                        escape(req.user_agent().unwrap_or("-")),
+                    ));
+                }
+                line
+            }
+            LogFormat::Json => json!({
+                "time": started.format(&Rfc3339).unwrap_or_default(),
+                "method": req.method(),
+                "path": target,
+                "status": resp.get_status(),
+                "bytes": resp.get_body_len(),
+                "duration_ms": duration.as_secs_f64() * 1000.0,
+                "remote_ip": req.remote_ip(),
+                "user_agent": req.user_agent(),
+                "request_id": request_id,
+            })
+            .to_string(),
+        }
+    }
+
+    /// Write a line to the configured output
+    fn write(&self, line: String) {
+        match &self.output {
+            Output::Log => log::info!(target: "dia::access", "{}", line),
+            Output::Stdout => println!("{}", line),
+            Output::File(path) => {
+                let writer = self.writer.get_or_init(|| {
+                    let file = RotatingFile {
+                        path: path.clone(),
+                        file: None,
+                        size: 0,
+                        max_size: self.max_file_size,
+                        max_files: self.max_files,
+                    };
+                    file.spawn()
+                        .inspect_err(|err| log::error!("Failed to start access log writer: {}", err))
+                        .ok()
+                });
+                match writer.as_ref().map(|writer| writer.try_send(line)) {
+                    Some(Ok(())) | None => {}
+                    Some(Err(TrySendError::Full(_))) => {
+                        log::warn!("Access log writer is behind, dropping a line for {}", path.display());
+                    }
+                    Some(Err(TrySendError::Disconnected(_))) => {
+                        log::error!("Access log writer for {} has stopped", path.display());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for AccessLog {
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        let request_id = req
+            .header("x-request-id")
+            .filter(|id| !id.is_empty())
+            .map(|id| RequestId(id.to_string()))
+            .unwrap_or_else(RequestId::generate);
+        req.extensions_mut().insert(request_id);
+        req.extensions_mut().insert(RequestStart(Instant::now(), OffsetDateTime::now_utc()));
+        Box::pin(async { None })
+    }
+
+    fn after_request<'a>(
+        &'a self,
+        req: &'a Request,
+        mut resp: Response,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
+        let (started, duration) = req
+            .extensions()
+            .get::<RequestStart>()
+            .map(|start| (start.1, start.0.elapsed()))
+            .unwrap_or_else(|| (OffsetDateTime::now_utc(), Duration::ZERO));
+        if let Some(id) = req.extensions().get::<RequestId>() {
+            resp = resp.header(REQUEST_ID_HEADER, id.0.clone());
+        }
+
+        self.write(self.line(req, &resp, started, duration));
+        Box::pin(async move { resp })
+    }
+
+    fn name(&self) -> &str {
+        "AccessLog"
+    }
+}
+
+/// Escape a header value for a quoted log field
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller;
+    use crate::middleware::MiddlewareChain;
+    use crate::response::ResponseBody;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+
+    #[actix_web::test]
+    async fn unmatched_routes_are_logged_to_a_rotated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        // Limits set before the file still apply
+        let log = AccessLog::new().max_file_size(1).max_files(1).file(&path);
+        let chain = MiddlewareChain::new(vec![Box::new(log)]);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(chain))
+                .default_service(web::to(controller::not_found)),
+        )
+        .await;
+
+        for uri in ["/first", "/second"] {
+            let resp = call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), 404);
+            assert!(resp.headers().contains_key("x-request-id"));
+        }
+
+        // Lines are written by the writer thread
+        let rotated = dir.path().join("access.log.1");
+        for _ in 0..200 {
+            if fs::read_to_string(&path).is_ok_and(|log| log.contains("/second")) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(fs::read_to_string(&rotated).unwrap().contains("\"GET /first HTTP/1.1\" 404 9"));
+        assert!(fs::read_to_string(&path).unwrap().contains("\"GET /second HTTP/1.1\" 404 9"));
+        assert!(!dir.path().join("access.log.2").exists());
+    }
+
+    #[test]
+    fn sensitive_query_values_are_redacted() {
+        let req = TestRequest::get()
+            .uri("/invoices?page=2&api%5Fkey=k_live_1234&api_key&key=x")
+            .to_http_request();
+        let mut req = Request::new(req);
+        SensitiveQuery::mark(&mut req, "api_key");
+
+        let line = AccessLog::new().line(&req, &Response::new(), OffsetDateTime::now_utc(), Duration::ZERO);
+        assert!(line.contains("GET /invoices?page=2&api%5Fkey=REDACTED&api_key=REDACTED&key=x "), "{}", line);
+    }
+
+    /// Request from 203.0.113.7 with a referer and a user agent needing escapes
+    fn browser_request() -> Request {
+        let req = TestRequest::get()
+            .uri("/search?q=dia")
+            .peer_addr("203.0.113.7:5000".parse().unwrap())
+            .insert_header(("referer", "https://example.com/"))
+            .insert_header(("user-agent", r#"Agent "quoted" \ 1.0"#))
+            .to_http_request();
+        Request::new(req)
+    }
+
+    /// Format a line for a request started at 2023-11-14 22:13:20 UTC
+    fn line_for(format: LogFormat, req: &Request, resp: &Response) -> String {
+        let started = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        AccessLog::new().format(format).line(req, resp, started, Duration::from_millis(12))
+    }
+
+    #[test]
+    fn common_lines() {
+        let resp = Response::ok_text("hello");
+        assert_eq!(
+            line_for(LogFormat::Common, &browser_request(), &resp),
+            r#"203.0.113.7 - - [14/Nov/2023:22:13:20 +0000] "GET /search?q=dia HTTP/1.1" 200 5"#
+        );
+
+        // Streams have no known length
+        let chunks = futures::stream::iter(vec![Ok::<_, std::io::Error>(actix_web::web::Bytes::from("x"))]);
+        let resp = Response::new().status(201).stream(chunks);
+        let req = Request::new(TestRequest::post().uri("/upload").to_http_request());
+        assert_eq!(
+            line_for(LogFormat::Common, &req, &resp),
+            r#"- - - [14/Nov/2023:22:13:20 +0000] "POST /upload HTTP/1.1" 201 -"#
+        );
+    }
+
+    #[test]
+    fn combined_lines_escape_quotes() {
+        assert_eq!(
+            line_for(LogFormat::Combined, &browser_request(), &Response::ok_text("hello")),
+            r#"203.0.113.7 - - [14/Nov/2023:22:13:20 +0000] "GET /search?q=dia HTTP/1.1" 200 5 "https://example.com/" "Agent \"quoted\" \\ 1.0""#
+        );
+
+        let req = Request::new(TestRequest::get().uri("/").to_http_request());
+        assert!(line_for(LogFormat::Combined, &req, &Response::new()).ends_with(r#"200 0 "-" "-""#));
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut req = browser_request();
+        req.extensions_mut().insert(RequestId("req-1".to_string()));
+        assert_eq!(
+            line_for(LogFormat::Json, &req, &Response::not_found()),
+            r#"{"bytes":9,"duration_ms":12.0,"method":"GET","path":"/search?q=dia","remote_ip":"203.0.113.7","request_id":"req-1","status":404,"time":"2023-11-14T22:13:20Z","user_agent":"Agent \"quoted\" \\ 1.0"}"#
+        );
+    }
+
+    #[test]
+    fn request_ids_are_taken_or_generated_and_echoed() {
+        let chain = MiddlewareChain::new(vec![Box::new(AccessLog::new())]);
+        let handler: controller::HandlerFn = std::sync::Arc::new(|req, _resp| {
+            Box::pin(async move {
+                let id = req.extensions().get::<RequestId>().map(|id| id.0.clone()).unwrap_or_default();
+                Response::ok_text(id)
+            })
+        });
+        let call = |req: TestRequest| {
+            let resp = futures::executor::block_on(chain.handle(Request::new(req.to_http_request()), handler.clone()));
+            let header = resp.get_header("x-request-id").map(str::to_string);
+            let ResponseBody::Text(seen) = resp.get_body() else {
+                panic!("unexpected body {:?}", resp.get_body());
+            };
+            (seen.clone(), header)
+        };
+
+        let (seen, header) = call(TestRequest::get().insert_header(("x-request-id", "abc-123")));
+        assert_eq!(seen, "abc-123");
+        assert_eq!(header.as_deref(), Some("abc-123"));
+
+        let (first, header) = call(TestRequest::get());
+        assert!(!first.is_empty());
+        assert_eq!(header, Some(first.clone()));
+        let (second, _) = call(TestRequest::get());
+        assert_ne!(first, second);
+    }
+}
@@ -2,14 +2,32 @@
 //! 
 //! Provides the main Application struct for setting up and running web servers.
 
-use actix_web::{web, App, HttpServer, middleware::Logger};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{Service, ServerHandle};
+use actix_web::web::Bytes;
+use actix_web::{web, App, HttpServer};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::collections::HashMap;
-use anyhow::Result;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use anyhow::{anyhow, Result};
 use log::info;
+use tokio::sync::{watch, Notify};
 
-use crate::controller::Controller;
-use crate::middleware::Middleware;
+use crate::access_log::AccessLog;
+use crate::controller::{self, BasicController, Controller, Route};
+use crate::cookie::CookieKey;
+use crate::extensions::Extensions;
+use crate::middleware::{Middleware, MiddlewareChain};
+use crate::request::BodyConfig;
+
+/// String values set with `Application::state`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AppState(pub(crate) HashMap<String, String>);
 
 /// Main application struct that holds the web server configuration
 pub struct Application {
@@ -19,10 +37,18 @@ pub struct Application {
     port: u16,
     /// Registered controllers
     controllers: Vec<Arc<dyn Controller>>,
+    /// Routes registered on the application itself
+    routes: Vec<Route>,
     /// Registered middlewares
     middlewares: Vec<Box<dyn Middleware>>,
-    /// Application state
-    state: HashMap<String, String>,
+    /// Access logger wrapping all middlewares
+    access_log: Option<AccessLog>,
+    /// Typed values shared with handlers, including the `state` map
+    data: Extensions,
+    /// Request body settings
+    body_config: BodyConfig,
+    /// Key for signed and encrypted cookies
+    cookie_key: Option<CookieKey>,
 }
 
 impl Application {
@@ -40,38 +66,166 @@ impl Application {
             host: "127.0.0.1".to_string(),
             port: 8080,
             controllers: Vec::new(),
+            routes: Vec::new(),
             middlewares: Vec::new(),
-            state: HashMap::new(),
+            access_log: Some(AccessLog::new()),
+            data: Extensions::new(),
+            body_config: BodyConfig::default(),
+            cookie_key: None,
         }
     }
 
     /// Set the host address for the server
     pub fn host<S: Into<String>>(mut self, host: S) -> Self {
-        self.host = host.into();
+        self.set_host(host);
         self
     }
 
     /// Set the port for the server
     pub fn port(mut self, port: u16) -> Self {
-        self.port = port;
+        self.set_port(port);
         self
     }
 
+    /// Set the host address in place
+    pub fn set_host<S: Into<String>>(&mut self, host: S) {
+        self.host = host.into();
+    }
+
+    /// Set the port in place
+    pub fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    /// Get the host address the server binds to
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get the port the server binds to
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+
     /// Add a controller to the application
     pub fn controller<C: Controller + 'static>(mut self, controller: C) -> Self {
-        self.controllers.push(Arc::new(controller));
+        self.add_controller(controller);
+        self
+    }
+
+    /// Add a route outside any controller
+    pub fn route(mut self, route: Route) -> Self {
+        self.add_route(route);
         self
     }
 
+    /// Add a controller in place
+    pub fn add_controller<C: Controller + 'static>(&mut self, controller: C) {
+        self.controllers.push(Arc::new(controller));
+    }
+
+    /// Add a route outside any controller, in place
+    pub fn add_route(&mut self, route: Route) {
+        self.routes.push(route);
+    }
+
     /// Add middleware to the application
+    ///
+    /// Middlewares wrap every controller route in registration order: the
+    /// first one added is the outermost layer.
     pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.add_middleware(middleware);
+        self
+    }
+
+    /// Add middleware to the application, in place
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
         self.middlewares.push(Box::new(middleware));
+    }
+
+    /// Set the access logger
+    ///
+    /// The access logger is the outermost middleware, so it records responses
+    /// produced by every other middleware. Defaults to Common Log Format
+    /// through the `log` crate.
+    pub fn access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    /// Disable access logging
+    pub fn without_access_log(mut self) -> Self {
+        self.access_log = None;
         self
     }
 
     /// Set application state
+    ///
+    /// Handlers read it with `Request::state`. The map is shared like the
+    /// values passed to `data`.
     pub fn state<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
-        self.state.insert(key.into(), value.into());
+        let mut state = self.data.get::<AppState>().cloned().unwrap_or_default();
+        state.0.insert(key.into(), value.into());
+        self.data.insert(state);
+        self
+    }
+
+    /// Share a typed value with every handler
+    ///
+    /// Handlers read it with `Request::data`. Values are shared, not cloned,
+    /// so use interior mutability for state that changes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use dia_core::{Application, TokenService};
+    /// use dia_core::jwt::JwtConfig;
+    ///
+    /// let app = Application::new().data(TokenService::new(JwtConfig::hmac("secret")));
+    /// ```
+    pub fn data<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.data.insert(value);
+        self
+    }
+
+    /// Set the maximum request body size in bytes
+    ///
+    /// Larger bodies are rejected with `413 Payload Too Large`. Multipart
+    /// bodies use `max_part_size` and `max_multipart_size` instead.
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.body_config.max_size = bytes;
+        self
+    }
+
+    /// Set the maximum size of a single multipart part in bytes
+    pub fn max_part_size(mut self, bytes: usize) -> Self {
+        self.body_config.max_part_size = bytes;
+        self
+    }
+
+    /// Set the maximum total size of a multipart body in bytes
+    pub fn max_multipart_size(mut self, bytes: usize) -> Self {
+        self.body_config.max_multipart_size = bytes;
+        self
+    }
+
+    /// Set the size above which multipart parts are spilled to a temporary file
+    pub fn part_memory_limit(mut self, bytes: usize) -> Self {
+        self.body_config.part_memory_limit = bytes;
+        self
+    }
+
+    /// Set the secret used to sign and encrypt cookies
+    ///
+    /// Enables `Request::signed_cookie`/`private_cookie` and the matching
+    /// `Response` builders. The secret should be random and kept stable
+    /// across restarts, or existing cookies become unreadable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the secret is shorter than 32 bytes.
+    pub fn cookie_secret<S: AsRef<[u8]>>(mut self, secret: S) -> Self {
+        self.cookie_key = Some(CookieKey::derive_from(secret.as_ref()));
         self
     }
 
@@ -81,7 +235,7 @@ impl Application {
     /// 
     /// # Examples
     /// 
-    /// ```rust
+    /// ```rust,no_run
     /// use dia_core::Application;
     /// 
     /// #[tokio::main]
@@ -95,19 +249,101 @@ impl Application {
     /// }
     /// ```
     pub async fn run(self) -> Result<()> {
+        let (server, _, _) = self.serve(false)?;
+        server.await?;
+        Ok(())
+    }
+
+    /// Start the application server in the background
+    ///
+    /// Binds and returns immediately with a `Server` handle to read the bound
+    /// address, stop the server and wait for it to finish. Unlike `run`, the
+    /// server does not stop on Ctrl-C: it runs until `Server::stop`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use dia_core::Application;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     // Port 0 picks a free port
+    ///     let server = Application::new().port(0).start()?;
+    ///     assert_ne!(server.local_addr().port(), 0);
+    ///
+    ///     server.stop(true, Duration::from_secs(5)).await;
+    ///     server.wait().await
+    /// }
+    /// ```
+    pub fn start(self) -> Result<Server> {
+        let (server, addrs, in_flight) = self.serve(true)?;
+        let handle = server.handle();
+        let (done_tx, done_rx) = watch::channel(None);
+        tokio::spawn(async move {
+            let _ = done_tx.send(Some(server.await.map_err(Arc::new)));
+        });
+
+        Ok(Server {
+            handle,
+            addrs,
+            in_flight,
+            done: done_rx,
+        })
+    }
+
+    /// Bind the HTTP server
+    ///
+    /// Background servers leave signal handling to their owner.
+    fn serve(self, background: bool) -> Result<(actix_web::dev::Server, Vec<SocketAddr>, Arc<InFlight>)> {
         let bind_address = format!("{}:{}", self.host, self.port);
-        info!("Starting dia server on {}", bind_address);
+        for controller in &self.controllers {
+            controller.validate()?;
+        }
 
-        let state = Arc::new(self.state);
-        let controllers = self.controllers;
+        let mut controllers = self.controllers;
+        if !self.routes.is_empty() {
+            let routes = self.routes.into_iter().fold(BasicController::new(), BasicController::route);
+            controllers.push(Arc::new(routes));
+        }
+        let mut middlewares = self.middlewares;
+        if let Some(access_log) = self.access_log {
+            middlewares.insert(0, Box::new(access_log));
+        }
+        let middlewares = MiddlewareChain::new(middlewares);
+        middlewares.validate()?;
+        let middlewares = web::Data::new(middlewares);
+        info!("Starting dia server on {}", bind_address);
+        let body_config = web::Data::new(self.body_config);
+        let data = web::Data::new(self.data);
+        let cookie_key = self.cookie_key.map(web::Data::new);
+        let in_flight = Arc::new(InFlight::default());
+        let requests = in_flight.clone();
 
-        HttpServer::new(move || {
+        let mut server = HttpServer::new(move || {
+            let requests = requests.clone();
             let mut app = App::new()
-                .app_data(web::Data::new(state.clone()))
-                .wrap(Logger::default());
+                .wrap_fn(move |req, srv| {
+                    let guard = requests.enter();
+                    let response = srv.call(req);
+                    async move {
+                        // Streamed and file bodies are still being sent when
+                        // the handler returns, so the body holds the guard
+                        let response = response.await?;
+                        Ok(response.map_body(|_, body| InFlightBody { body, guard: Some(guard) }))
+                    }
+                })
+                .app_data(middlewares.clone())
+                .app_data(body_config.clone())
+                .app_data(data.clone());
 
-            // Apply middlewares
-            // TODO: Apply custom middlewares here
+            if let Some(key) = &cookie_key {
+                app = app.app_data(key.clone());
+            }
 
             // Register controllers
             for controller in &controllers {
@@ -116,13 +352,133 @@ impl Application {
                 });
             }
 
-            app
+            app.default_service(web::to(controller::not_found))
         })
-        .bind(&bind_address)?
-        .run()
-        .await?;
+        .bind(&bind_address)?;
+        if background {
+            server = server.disable_signals();
+        }
 
-        Ok(())
+        let addrs = server.addrs();
+        Ok((server.run(), addrs, in_flight))
+    }
+}
+
+/// Requests being handled, so graceful stops know when to close
+#[derive(Default)]
+struct InFlight {
+    /// Number of requests being handled
+    count: AtomicUsize,
+    /// Notified when the count drops to zero
+    idle: Notify,
+}
+
+impl InFlight {
+    /// Count a request until the guard is dropped
+    fn enter(self: &Arc<Self>) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.clone())
+    }
+
+    /// Wait until no request is being handled
+    async fn wait_idle(&self) {
+        loop {
+            let idle = self.idle.notified();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+/// Counts a request in `InFlight` while alive
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Response body counted in `InFlight` until it has been sent
+struct InFlightBody<B> {
+    /// Wrapped body
+    body: B,
+    /// Released once the body ends or fails
+    guard: Option<InFlightGuard>,
+}
+
+impl<B: MessageBody + Unpin> MessageBody for InFlightBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<std::result::Result<Bytes, Self::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_next(cx);
+        if let Poll::Ready(None | Some(Err(_))) = poll {
+            self.guard = None;
+        }
+        poll
+    }
+}
+
+/// Handle to a server started with `Application::start`
+pub struct Server {
+    /// actix-web server handle
+    handle: ServerHandle,
+    /// Bound addresses
+    addrs: Vec<SocketAddr>,
+    /// Requests being handled
+    in_flight: Arc<InFlight>,
+    /// Result of the server, once it has stopped
+    done: watch::Receiver<Option<std::result::Result<(), Arc<io::Error>>>>,
+}
+
+impl Server {
+    /// Get the address the server is bound to
+    ///
+    /// With port 0, this is where the server actually listens.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addrs[0]
+    }
+
+    /// Get every address the server is bound to
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// Stop the server
+    ///
+    /// A graceful stop stops accepting connections and waits up to `timeout`
+    /// for requests in progress to finish, including streamed and file bodies
+    /// still being sent; the remaining connections are then closed. Otherwise
+    /// all connections are closed right away.
+    pub async fn stop(&self, graceful: bool, timeout: Duration) {
+        if graceful {
+            self.handle.pause().await;
+            if tokio::time::timeout(timeout, self.in_flight.wait_idle()).await.is_err() {
+                log::warn!("Requests still in progress after {:?}, closing connections", timeout);
+            }
+        }
+        self.handle.stop(false).await;
+    }
+
+    /// Wait until the server has stopped
+    pub async fn wait(&self) -> Result<()> {
+        let mut done = self.done.clone();
+        let result = match done.wait_for(Option::is_some).await {
+            Ok(result) => result.clone(),
+            Err(_) => return Err(anyhow!("Server task ended unexpectedly")),
+        };
+        match result {
+            Some(Err(err)) => Err(err.into()),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -130,4 +486,45 @@ impl Default for Application {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+    use futures::stream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn graceful_stops_wait_for_streamed_bodies() {
+        let route = Route::get("/slow", Arc::new(|_req, resp: Response| {
+            Box::pin(async move {
+                let chunks = stream::unfold(0, |sent| async move {
+                    if sent == 3 {
+                        return None;
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Some((Ok::<_, io::Error>(Bytes::from(format!("chunk{};", sent))), sent + 1))
+                });
+                resp.stream(chunks)
+            }) as _
+        }));
+        let server = Application::new().port(0).without_access_log().route(route).start().unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(server.local_addr()).await.unwrap();
+        stream.write_all(b"GET /slow HTTP/1.1\r\nHost: dia\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut head = [0; 12];
+        stream.read_exact(&mut head).await.unwrap();
+        assert_eq!(&head, b"HTTP/1.1 200");
+
+        // The handler has returned, but the body is still streaming
+        let (_, response) = tokio::join!(server.stop(true, Duration::from_secs(5)), async {
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        });
+        assert!(response.contains("chunk0;"));
+        assert!(response.contains("chunk2;"));
+        server.wait().await.unwrap();
+    }
+}
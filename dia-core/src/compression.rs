@@ -0,0 +1,345 @@
+//! Compression module for dia framework
+//!
+//! Provides the CompressionMiddleware, compressing response bodies with
+//! gzip, deflate or brotli as negotiated from `Accept-Encoding`, and the
+//! decoding of compressed request bodies.
+
+use crate::files::encoding_quality;
+use crate::middleware::Middleware;
+use crate::request::BodyError;
+use crate::response::ResponseBody;
+use crate::{Request, Response};
+use actix_web::web::{self, Bytes};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+
+/// Default size under which bodies are sent uncompressed (1 KiB)
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// Size from which coding runs on the blocking thread pool instead of the
+/// worker (64 KiB)
+const BLOCKING_SIZE: usize = 64 * 1024;
+
+/// Content types whose data is compressed already
+const COMPRESSED_TYPES: [&str; 12] = [
+    "image/",
+    "audio/",
+    "video/",
+    "font/woff",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-7z-compressed",
+    "application/x-rar-compressed",
+    "application/zstd",
+    "application/pdf",
+];
+
+/// Content coding applied to a body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// `br`
+    Brotli,
+    /// `gzip`
+    Gzip,
+    /// `deflate` (zlib-wrapped, as browsers expect)
+    Deflate,
+}
+
+impl Encoding {
+    /// Name used in `Accept-Encoding` and `Content-Encoding`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// Compress data
+    fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut out = Vec::new();
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 5,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &data[..], &mut out, &params)?;
+                Ok(out)
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Middleware compressing response bodies
+///
+/// Text, JSON and binary bodies of at least `min_size` bytes are compressed
+/// with the coding the client prefers among the configured ones, unless
+/// their content type is compressed already (images, archives, ...) or the
+/// handler set `Content-Encoding` itself. Streams and files are sent as-is;
+/// use `SpaFiles::precompressed` for static assets.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::Application;
+/// use dia_core::compression::{CompressionMiddleware, Encoding};
+///
+/// let app = Application::new().middleware(
+///     CompressionMiddleware::new()
+///         .min_size(4096)
+///         .encodings(vec![Encoding::Gzip, Encoding::Deflate]),
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompressionMiddleware {
+    /// Size under which bodies are sent uncompressed
+    min_size: usize,
+    /// Supported codings, preferred first
+    encodings: Vec<Encoding>,
+}
+
+impl CompressionMiddleware {
+    /// Create a compression middleware supporting brotli, gzip and deflate
+    pub fn new() -> Self {
+        Self {
+            min_size: DEFAULT_MIN_SIZE,
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+        }
+    }
+
+    /// Set the size in bytes under which bodies are sent uncompressed
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Set the supported codings, preferred first when the client has no
+    /// preference
+    pub fn encodings(mut self, encodings: Vec<Encoding>) -> Self {
+        self.encodings = encodings;
+        self
+    }
+
+    /// Pick the coding with the highest quality in `Accept-Encoding`
+    fn negotiate(&self, accept: &str) -> Option<Encoding> {
+        let mut best: Option<(Encoding, f32)> = None;
+        for encoding in &self.encodings {
+            let quality = encoding_quality(accept, encoding.name());
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((*encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+impl Default for CompressionMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for CompressionMiddleware {
+    fn after_request<'a>(
+        &'a self,
+        req: &'a Request,
+        resp: Response,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
+        Box::pin(compress(resp, req.header("accept-encoding"), self))
+    }
+
+    fn name(&self) -> &str {
+        "Compression"
+    }
+}
+
+/// Compress a response body if it is eligible and the client accepts it
+async fn compress(mut resp: Response, accept: Option<&str>, config: &CompressionMiddleware) -> Response {
+    let eligible = matches!(
+        resp.get_body(),
+        ResponseBody::Text(_) | ResponseBody::Json(_) | ResponseBody::Binary(_)
+    ) && !matches!(resp.get_status(), 204 | 206 | 304)
+        && resp.get_header("content-encoding").is_none()
+        && !resp.get_header("content-type").is_some_and(is_compressed_type)
+        && resp.get_body_len().is_some_and(|len| len >= config.min_size as u64);
+    if !eligible {
+        return resp;
+    }
+
+    // The representation depends on Accept-Encoding even when sent as-is
+    resp = resp.append_header("Vary", "Accept-Encoding");
+    let Some(encoding) = accept.and_then(|accept| config.negotiate(accept)) else {
+        return resp;
+    };
+
+    // Reuse the JSON bytes serialized while measuring the body
+    let json = resp.json_bytes().cloned();
+    let data = match resp.replace_body(ResponseBody::Empty) {
+        ResponseBody::Text(text) => Bytes::from(text),
+        ResponseBody::Json(_) => json.unwrap_or_default(),
+        ResponseBody::Binary(data) => Bytes::from(data),
+        _ => unreachable!("checked above"),
+    };
+    let compressed = if data.len() < BLOCKING_SIZE {
+        encoding.encode(&data)
+    } else {
+        let input = data.clone();
+        web::block(move || encoding.encode(&input))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err.to_string())))
+    };
+    match compressed {
+        Ok(compressed) => {
+            resp.replace_body(ResponseBody::Binary(compressed));
+            // Strong validators identify the uncompressed bytes
+            if let Some(etag) = resp.get_header("etag").filter(|etag| !etag.starts_with("W/")) {
+                let weak = format!("W/{}", etag);
+                resp = resp.header("ETag", weak);
+            }
+            resp.header("Content-Encoding", encoding.name())
+        }
+        Err(err) => {
+            log::warn!("Failed to {} response body: {}", encoding.name(), err);
+            resp.replace_body(ResponseBody::Binary(Vec::from(data)));
+            resp
+        }
+    }
+}
+
+/// Check if a content type is compressed already
+fn is_compressed_type(content_type: &str) -> bool {
+    let content_type = content_type.trim().to_ascii_lowercase();
+    !content_type.starts_with("image/svg") && COMPRESSED_TYPES.iter().any(|prefix| content_type.starts_with(prefix))
+}
+
+/// Decode a request body sent with `Content-Encoding`
+///
+/// The work is bounded by the decoded size rather than the compressed one,
+/// so decoding runs on the blocking thread pool unless `limit` is small.
+pub(crate) async fn decode_request_body(
+    content_encoding: &str,
+    body: Bytes,
+    limit: usize,
+) -> Result<Bytes, BodyError> {
+    if limit < BLOCKING_SIZE {
+        return decode_body(content_encoding, body, limit);
+    }
+    let content_encoding = content_encoding.to_string();
+    web::block(move || decode_body(&content_encoding, body, limit))
+        .await
+        .unwrap_or_else(|err| Err(BodyError::Payload(err.to_string())))
+}
+
+/// Decode a request body sent with `Content-Encoding`
+///
+/// Codings are undone in reverse order; the decoded body must stay within
+/// `limit` bytes.
+fn decode_body(content_encoding: &str, body: Bytes, limit: usize) -> Result<Bytes, BodyError> {
+    let mut body = body;
+    for coding in content_encoding.rsplit(',').map(str::trim).filter(|coding| !coding.is_empty()) {
+        let coding = coding.to_ascii_lowercase();
+        let reader: Box<dyn Read + '_> = match coding.as_str() {
+            "identity" => continue,
+            "gzip" | "x-gzip" => Box::new(GzDecoder::new(&body[..])),
+            "deflate" => Box::new(ZlibOrRawDeflate::new(&body)),
+            "br" => Box::new(brotli::Decompressor::new(&body[..], 4096)),
+            _ => return Err(BodyError::UnsupportedEncoding(coding)),
+        };
+
+        let mut decoded = Vec::new();
+        reader
+            .take(limit as u64 + 1)
+            .read_to_end(&mut decoded)
+            .map_err(|err| BodyError::InvalidEncoding(format!("{}: {}", coding, err)))?;
+        if decoded.len() > limit {
+            return Err(BodyError::TooLarge { limit });
+        }
+        body = Bytes::from(decoded);
+    }
+    Ok(body)
+}
+
+/// Reader for `deflate` bodies, which clients send zlib-wrapped or raw
+enum ZlibOrRawDeflate<'a> {
+    /// zlib stream, as the specification requires
+    Zlib(ZlibDecoder<&'a [u8]>),
+    /// Raw deflate stream, as some clients send
+    Raw(DeflateDecoder<&'a [u8]>),
+}
+
+impl<'a> ZlibOrRawDeflate<'a> {
+    /// Pick the decoder from the zlib header check bits
+    fn new(data: &'a [u8]) -> Self {
+        match data {
+            [cmf, flg, ..] if cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0 => {
+                Self::Zlib(ZlibDecoder::new(data))
+            }
+            _ => Self::Raw(DeflateDecoder::new(data)),
+        }
+    }
+}
+
+impl Read for ZlibOrRawDeflate<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Zlib(decoder) => decoder.read(buf),
+            Self::Raw(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_prefers_quality_then_configured_order() {
+        let compression = CompressionMiddleware::new();
+        assert_eq!(compression.negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(compression.negotiate("gzip;q=1, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(compression.negotiate("*;q=0.1, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(compression.negotiate("identity"), None);
+    }
+
+    #[test]
+    fn request_bodies_round_trip() {
+        for encoding in [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate] {
+            let body = Bytes::from(encoding.encode(b"hello dia").unwrap());
+            assert_eq!(decode_body(encoding.name(), body, 1024).unwrap(), "hello dia");
+        }
+        let body = Bytes::from(Encoding::Gzip.encode(&[b'a'; 2048]).unwrap());
+        assert!(matches!(decode_body("gzip", body, 1024), Err(BodyError::TooLarge { limit: 1024 })));
+    }
+
+    #[actix_web::test]
+    async fn large_bodies_are_coded_off_the_worker() {
+        let text = "dia ".repeat(BLOCKING_SIZE);
+        let resp = compress(Response::ok_text(text.clone()), Some("gzip"), &CompressionMiddleware::new()).await;
+        assert_eq!(resp.get_header("content-encoding"), Some("gzip"));
+        let ResponseBody::Binary(compressed) = resp.get_body() else {
+            panic!("expected a compressed body");
+        };
+
+        let decoded = decode_request_body("gzip", Bytes::from(compressed.clone()), text.len()).await.unwrap();
+        assert_eq!(decoded, text);
+        let too_large = decode_request_body("gzip", Bytes::from(compressed.clone()), BLOCKING_SIZE).await;
+        assert!(matches!(too_large, Err(BodyError::TooLarge { .. })));
+    }
+}
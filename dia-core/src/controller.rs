@@ -2,8 +2,17 @@
 //! 
 //! Provides the Controller trait and routing functionality.
 
+use crate::compression;
+use crate::cookie::CookieKey;
+use crate::extensions::Extensions;
+use crate::middleware::{self, ConfigError, Middleware, MiddlewareChain};
+use crate::multipart;
+use crate::request::{BodyConfig, BodyError};
 use crate::{Request, Response};
-use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::http::Method;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::StreamExt;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -20,6 +29,13 @@ pub trait Controller: Send + Sync {
     fn base_path(&self) -> Option<&str> {
         None
     }
+
+    /// Check the configuration of the controller's middlewares
+    ///
+    /// Called before the server starts; see `Middleware::validate`.
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
 }
 
 /// Route definition struct
@@ -34,6 +50,15 @@ pub struct Route {
 }
 
 impl Route {
+    /// Create a route for any HTTP method
+    pub fn new<M: Into<String>, S: Into<String>>(method: M, path: S, handler: HandlerFn) -> Self {
+        Self {
+            method: method.into().to_ascii_uppercase(),
+            path: path.into(),
+            handler,
+        }
+    }
+
     /// Create a new GET route
     pub fn get<S: Into<String>>(path: S, handler: HandlerFn) -> Self {
         Self {
@@ -81,11 +106,14 @@ impl Route {
 }
 
 /// Basic controller implementation that holds routes
+#[derive(Clone)]
 pub struct BasicController {
     /// List of routes
     routes: Vec<Route>,
     /// Base path for all routes in this controller
     base_path: Option<String>,
+    /// Middlewares wrapped around this controller's routes
+    middlewares: Vec<Arc<dyn Middleware>>,
 }
 
 impl BasicController {
@@ -94,6 +122,7 @@ impl BasicController {
         Self {
             routes: Vec::new(),
             base_path: None,
+            middlewares: Vec::new(),
         }
     }
 
@@ -105,10 +134,29 @@ impl BasicController {
 
     /// Add a route to this controller
     pub fn route(mut self, route: Route) -> Self {
+        self.add_route(route);
+        self
+    }
+
+    /// Add a route to this controller, in place
+    pub fn add_route(&mut self, route: Route) {
         self.routes.push(route);
+    }
+
+    /// Add middleware to this controller
+    ///
+    /// Controller middlewares run inside the application's middlewares and
+    /// only around this controller's routes, in registration order.
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.add_middleware(middleware);
         self
     }
 
+    /// Add middleware to this controller, in place
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.middlewares.push(Arc::new(middleware));
+    }
+
     /// Add a GET route
     pub fn get<F>(self, path: &str, handler: F) -> Self 
     where
@@ -152,6 +200,15 @@ impl BasicController {
 
 impl Controller for BasicController {
     fn register_routes(&self, config: &mut web::ServiceConfig) {
+        // Methods per path, for the OPTIONS routes answering preflights
+        let mut allowed: Vec<(String, Vec<String>)> = Vec::new();
+        let chain = MiddlewareChain::new(
+            self.middlewares
+                .iter()
+                .map(|middleware| Box::new(middleware.clone()) as Box<dyn Middleware>)
+                .collect(),
+        );
+
         for route in &self.routes {
             let full_path = if let Some(base) = &self.base_path {
                 format!("{}{}", base, route.path)
@@ -159,49 +216,282 @@ impl Controller for BasicController {
                 route.path.clone()
             };
 
-            let handler = route.handler.clone();
-            
+            let handler = if chain.is_empty() {
+                route.handler.clone()
+            } else {
+                with_chain(chain.clone(), route.handler.clone())
+            };
+
             // Convert our handler to actix-web handler
-            let actix_handler = move |req: HttpRequest| {
+            let actix_handler = move |req: HttpRequest, payload: web::Payload| {
                 let handler = handler.clone();
                 async move {
-                    let dia_req = Request::new(req);
-                    let dia_resp = Response::new();
-                    let result = handler(dia_req, dia_resp).await;
-                    Ok::<HttpResponse, actix_web::Error>(result.into_http_response())
+                    Ok::<HttpResponse, actix_web::Error>(dispatch(req, payload, handler).await)
                 }
             };
 
-            match route.method.as_str() {
-                "GET" => {
-                    config.route(&full_path, web::get().to(actix_handler));
-                }
-                "POST" => {
-                    config.route(&full_path, web::post().to(actix_handler));
-                }
-                "PUT" => {
-                    config.route(&full_path, web::put().to(actix_handler));
-                }
-                "DELETE" => {
-                    config.route(&full_path, web::delete().to(actix_handler));
-                }
-                "PATCH" => {
-                    config.route(&full_path, web::patch().to(actix_handler));
-                }
-                _ => {
+            let method = match Method::from_bytes(route.method.as_bytes()) {
+                Ok(method) => method,
+                Err(_) => {
                     log::warn!("Unsupported HTTP method: {}", route.method);
+                    continue;
                 }
+            };
+            config.route(&full_path, web::method(method).to(actix_handler));
+
+            match allowed.iter_mut().find(|(path, _)| *path == full_path) {
+                Some((_, methods)) => methods.push(route.method.clone()),
+                None => allowed.push((full_path, vec![route.method.clone()])),
+            }
+        }
+
+        // OPTIONS goes through the middleware chain too, so CorsMiddleware
+        // can answer preflights; otherwise it lists the allowed methods
+        for (path, mut methods) in allowed {
+            if methods.iter().any(|method| method == "OPTIONS") {
+                continue;
             }
+            methods.push("OPTIONS".to_string());
+            let allow = methods.join(", ");
+            let handler: HandlerFn = Arc::new(move |_req, _resp| {
+                let allow = allow.clone();
+                Box::pin(async move { Response::new().status(204).header("Allow", allow) })
+            });
+            let options_handler = move |req: HttpRequest, payload: web::Payload| {
+                let handler = handler.clone();
+                async move {
+                    Ok::<HttpResponse, actix_web::Error>(dispatch(req, payload, handler).await)
+                }
+            };
+            config.route(&path, web::method(Method::OPTIONS).to(options_handler));
         }
     }
 
     fn base_path(&self) -> Option<&str> {
         self.base_path.as_deref()
     }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        middleware::validate_all(self.middlewares.iter().map(|middleware| &**middleware))
+    }
+}
+
+/// Wrap a handler in a controller's middleware chain
+fn with_chain(chain: MiddlewareChain, handler: HandlerFn) -> HandlerFn {
+    Arc::new(move |req, _resp| {
+        let chain = chain.clone();
+        let handler = handler.clone();
+        Box::pin(async move { chain.handle(req, handler).await })
+    })
+}
+
+/// Build a dia request from actix-web and run it through the middleware chain
+///
+/// Custom `Controller` implementations can use this to get the same request
+/// handling as `BasicController`.
+pub async fn dispatch(req: HttpRequest, payload: web::Payload, handler: HandlerFn) -> HttpResponse {
+    let config = req
+        .app_data::<web::Data<BodyConfig>>()
+        .map(|config| config.get_ref().clone())
+        .unwrap_or_default();
+    let chain = req
+        .app_data::<web::Data<MiddlewareChain>>()
+        .map(|chain| chain.get_ref().clone())
+        .unwrap_or_default();
+    let cookie_key = req
+        .app_data::<web::Data<CookieKey>>()
+        .map(|key| key.get_ref().clone());
+
+    let mut dia_req = Request::new(req.clone());
+    if let Some(key) = &cookie_key {
+        dia_req.set_cookie_key(key.clone());
+    }
+    if let Some(data) = req.app_data::<web::Data<Extensions>>() {
+        dia_req.set_app_data(data.clone().into_inner());
+    }
+    // The body is read once every middleware let the request through, so
+    // authentication and rate limits apply before an upload is accepted
+    let (entered, short_circuit) = chain.before(&mut dia_req).await;
+    let resp = match short_circuit {
+        Some(resp) => resp,
+        None => match read_payload(&mut dia_req, &req, payload, &config).await {
+            Ok(()) => handler(dia_req.clone(), Response::new()).await.conditional(&dia_req),
+            Err(err) => Response::from(err),
+        },
+    };
+
+    chain
+        .after(&dia_req, entered, resp)
+        .await
+        .seal_cookies(cookie_key.as_ref())
+        .into_http_response()
+}
+
+/// Answer requests no route matched with 404 Not Found
+///
+/// Goes through the application middleware like any routed request, so the
+/// access log records it.
+pub(crate) async fn not_found(req: HttpRequest, payload: web::Payload) -> HttpResponse {
+    let handler: HandlerFn = Arc::new(|_req, _resp| Box::pin(async { Response::not_found() }));
+    dispatch(req, payload, handler).await
+}
+
+/// Read the request body into the request, as multipart parts or bytes
+async fn read_payload(
+    req: &mut Request,
+    http_req: &HttpRequest,
+    payload: web::Payload,
+    config: &BodyConfig,
+) -> Result<(), BodyError> {
+    if req.is_multipart() {
+        let parts = multipart::read(http_req.headers(), payload, config).await?;
+        req.set_multipart(parts);
+        Ok(())
+    } else {
+        let body = read_body(req, payload, config.max_size).await?;
+        req.set_body_bytes(body)
+    }
+}
+
+/// Read the request payload, enforcing the size limit and undoing any
+/// `Content-Encoding`
+async fn read_body(req: &Request, mut payload: web::Payload, limit: usize) -> Result<Bytes, BodyError> {
+    let declared = req
+        .header("content-length")
+        .and_then(|len| len.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > limit) {
+        return Err(BodyError::TooLarge { limit });
+    }
+
+    let mut body = BytesMut::with_capacity(declared.unwrap_or(0));
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| BodyError::Payload(e.to_string()))?;
+        if body.len() + chunk.len() > limit {
+            return Err(BodyError::TooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    match req.header("content-encoding") {
+        Some(encoding) => compression::decode_request_body(encoding, body.freeze(), limit).await,
+        None => Ok(body.freeze()),
+    }
 }
 
 impl Default for BasicController {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::Payload;
+    use actix_web::error::PayloadError;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::App;
+
+    /// Controller answering `POST /echo` with the JSON body it received
+    fn echo() -> BasicController {
+        BasicController::new().post("/echo", |req, _resp| {
+            Box::pin(async move {
+                match req.require_json() {
+                    Ok(json) => Response::ok_json(json),
+                    Err(err) => Response::from(err),
+                }
+            })
+        })
+    }
+
+    /// Body config accepting at most 16 bytes
+    fn small_bodies() -> web::Data<BodyConfig> {
+        web::Data::new(BodyConfig { max_size: 16, ..BodyConfig::default() })
+    }
+
+    #[actix_web::test]
+    async fn invalid_json_is_rejected() {
+        let app = init_service(App::new().configure(|cfg| echo().register_routes(cfg))).await;
+        let req = TestRequest::post()
+            .uri("/echo")
+            .insert_header(("content-type", "application/json"))
+            .set_payload("{\"id\": ")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        assert!(String::from_utf8_lossy(&read_body(resp).await).starts_with("Invalid JSON body"));
+    }
+
+    #[actix_web::test]
+    async fn declared_length_over_the_limit_is_rejected() {
+        let app = init_service(App::new().app_data(small_bodies()).configure(|cfg| echo().register_routes(cfg))).await;
+        let req = TestRequest::post()
+            .uri("/echo")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(format!("{{\"name\": \"{}\"}}", "x".repeat(32)))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 413);
+        assert_eq!(read_body(resp).await, "Request body exceeds the limit of 16 bytes");
+    }
+
+    #[actix_web::test]
+    async fn streamed_body_over_the_limit_is_rejected() {
+        let app = init_service(App::new().app_data(small_bodies()).configure(|cfg| echo().register_routes(cfg))).await;
+        let chunks: Pin<Box<dyn futures::Stream<Item = Result<Bytes, PayloadError>>>> =
+            Box::pin(futures::stream::iter(vec![Ok(Bytes::from("[1, 2, 3, ")), Ok(Bytes::from("4, 5, 6]"))]));
+        let (req, _) = TestRequest::post()
+            .uri("/echo")
+            .insert_header(("content-type", "application/json"))
+            .insert_header(("transfer-encoding", "chunked"))
+            .to_request()
+            .replace_payload(Payload::from(chunks));
+
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 413);
+    }
+
+    #[actix_web::test]
+    async fn unsupported_content_type_is_rejected() {
+        let app = init_service(App::new().configure(|cfg| echo().register_routes(cfg))).await;
+        let req = TestRequest::post()
+            .uri("/echo")
+            .insert_header(("content-type", "text/plain"))
+            .set_payload("{\"id\": 1}")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 415);
+        assert_eq!(
+            read_body(resp).await,
+            "Unsupported content type text/plain, expected application/json"
+        );
+    }
+
+    /// Controller answering `GET /users/{id}` with the raw and typed id
+    fn users() -> BasicController {
+        BasicController::new().get("/users/{id}", |req, _resp| {
+            Box::pin(async move {
+                let raw = req.param("id").cloned().unwrap_or_default();
+                match req.param_as::<u32>("id") {
+                    Ok(id) => Response::ok_text(format!("{} {}", raw, id + 1)),
+                    Err(err) => Response::from(err),
+                }
+            })
+        })
+    }
+
+    #[actix_web::test]
+    async fn path_params_come_from_the_route() {
+        let app = init_service(App::new().configure(|cfg| users().register_routes(cfg))).await;
+
+        let resp = call_service(&app, TestRequest::get().uri("/users/41").to_request()).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(read_body(resp).await, "41 42");
+
+        let resp = call_service(&app, TestRequest::get().uri("/users/abc").to_request()).await;
+        assert_eq!(resp.status(), 400);
+        assert_eq!(
+            read_body(resp).await,
+            "Invalid path parameter 'id' = 'abc': invalid digit found in string"
+        );
+    }
+}
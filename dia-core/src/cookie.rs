@@ -0,0 +1,131 @@
+//! Cookie module for dia framework
+//!
+//! Re-exports the cookie types used by Request and Response and provides the
+//! application key for signed and encrypted cookies.
+
+pub use actix_web::cookie::{time, Cookie, CookieBuilder, CookieJar, Expiration, Key, SameSite};
+
+use std::fmt;
+
+/// Key used to sign and encrypt cookies
+///
+/// Configured once on the application with `Application::cookie_secret` and
+/// handed to every request and response by the route adapter.
+#[derive(Clone)]
+pub struct CookieKey(Key);
+
+impl CookieKey {
+    /// Derive signing and encryption keys from an application secret
+    ///
+    /// # Panics
+    ///
+    /// Panics if the secret is shorter than 32 bytes.
+    pub fn derive_from(secret: &[u8]) -> Self {
+        Self(Key::derive_from(secret))
+    }
+
+    /// Generate a random key, valid until the process exits
+    pub fn generate() -> Self {
+        Self(Key::generate())
+    }
+
+    /// Get the underlying key
+    pub fn key(&self) -> &Key {
+        &self.0
+    }
+}
+
+impl fmt::Debug for CookieKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CookieKey(..)")
+    }
+}
+
+/// Parse the cookies of one `Cookie` request header, skipping malformed pairs
+pub(crate) fn parse_header(value: &str) -> impl Iterator<Item = Cookie<'static>> + '_ {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| Cookie::parse_encoded(pair.to_string()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use actix_web::test::TestRequest;
+
+    fn key() -> CookieKey {
+        CookieKey::derive_from(&[7; 32])
+    }
+
+    /// Seal a response's cookies and return its `Set-Cookie` headers
+    fn set_cookies(resp: Response, key: Option<&CookieKey>) -> Vec<String> {
+        let resp = resp.seal_cookies(key);
+        resp.get_headers().get_all("set-cookie").into_iter().map(str::to_string).collect()
+    }
+
+    /// Build a request sending a cookie back, with the application key
+    fn request_with(cookie: &str) -> Request {
+        let mut req = Request::new(TestRequest::get().insert_header(("cookie", cookie)).to_http_request());
+        req.set_cookie_key(key());
+        req
+    }
+
+    /// Turn a `Set-Cookie` value into the `name=value` pair a client sends
+    fn sent_back(set_cookie: &str) -> String {
+        Cookie::parse_encoded(set_cookie.to_string()).unwrap().stripped().encoded().to_string()
+    }
+
+    #[test]
+    fn signed_cookies_round_trip() {
+        let headers = set_cookies(Response::new().signed_cookie(Cookie::new("session", "alice")), Some(&key()));
+        let cookie = sent_back(&headers[0]);
+        assert_ne!(cookie, "session=alice");
+
+        let req = request_with(&cookie);
+        assert_eq!(req.signed_cookie("session").unwrap().value(), "alice");
+        assert!(req.private_cookie("session").is_none());
+    }
+
+    #[test]
+    fn tampered_cookies_are_rejected() {
+        let headers = set_cookies(Response::new().signed_cookie(Cookie::new("session", "alice")), Some(&key()));
+        let tampered = sent_back(&headers[0]).replace("alice", "admin");
+        assert!(request_with(&tampered).signed_cookie("session").is_none());
+
+        let headers = set_cookies(Response::new().private_cookie(Cookie::new("cart", "3 items")), Some(&key()));
+        let cookie = sent_back(&headers[0]);
+        assert!(!cookie.contains("items"));
+        assert_eq!(request_with(&cookie).private_cookie("cart").unwrap().value(), "3 items");
+
+        let mut tampered = cookie.into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(request_with(std::str::from_utf8(&tampered).unwrap()).private_cookie("cart").is_none());
+    }
+
+    #[test]
+    fn sealed_cookies_are_dropped_without_a_key() {
+        let resp = Response::new()
+            .cookie(Cookie::new("theme", "dark"))
+            .signed_cookie(Cookie::new("session", "alice"))
+            .private_cookie(Cookie::new("cart", "3"));
+        assert_eq!(set_cookies(resp, None), ["theme=dark"]);
+    }
+
+    #[test]
+    fn removed_cookies_keep_their_path_and_domain() {
+        let cookie = Cookie::build("session", "alice").path("/app").domain("example.com").finish();
+        let headers = set_cookies(Response::new().remove_cookie(cookie), None);
+        let removal = Cookie::parse(headers[0].clone()).unwrap();
+        assert_eq!(removal.value(), "");
+        assert_eq!(removal.path(), Some("/app"));
+        assert_eq!(removal.domain(), Some("example.com"));
+        assert_eq!(removal.max_age(), Some(time::Duration::ZERO));
+
+        let headers = set_cookies(Response::new().remove_cookie(Cookie::new("theme", "")), None);
+        assert_eq!(Cookie::parse(headers[0].clone()).unwrap().path(), Some("/"));
+    }
+}
@@ -0,0 +1,77 @@
+//! Extensions module for dia framework
+//!
+//! Provides typed per-request storage for values attached by middlewares.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Type map holding at most one value per type
+///
+/// Middlewares use it to hand values to handlers and to their own
+/// `after_request` hooks. Values are shared, so cloning is cheap.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::Extensions;
+///
+/// struct UserId(u64);
+///
+/// let mut extensions = Extensions::new();
+/// extensions.insert(UserId(42));
+///
+/// assert_eq!(extensions.get::<UserId>().map(|id| id.0), Some(42));
+/// assert!(extensions.get::<String>().is_none());
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    /// Values keyed by their type
+    map: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    /// Create an empty type map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a value, replacing any previous value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Get the value of a type
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Check if a value of a type is present
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Remove the value of a type, returning whether it was present
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> bool {
+        self.map.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Check if no values are stored
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions").field("len", &self.map.len()).finish()
+    }
+}
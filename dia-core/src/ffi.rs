@@ -1,11 +1,24 @@
 //! FFI module for dia framework
 //! 
 //! Provides C-compatible interfaces for Zig integration.
+//!
+//! Exported functions that take pointers are `unsafe` and document what they
+//! expect in a `# Safety` section. Null handles, strings and callbacks are
+//! checked and reported as -1 or null; any other pointer must be valid.
 
-use std::ffi::{CStr, CString};
+use std::ffi::{c_void, CStr};
+use std::future::Future;
 use std::os::raw::{c_char, c_int};
-use std::ptr;
-use crate::{Application, Response};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use actix_web::http::{Method, StatusCode};
+use actix_web::web;
+use crate::controller::HandlerFn;
+use crate::middleware::{CorsMiddleware, LoggingMiddleware, Middleware};
+use crate::response::ResponseBody;
+use crate::{Application, BasicController, Cookie, Request, Response, Route, Server};
+use serde_json::Value;
 
 /// Opaque pointer to Application instance
 #[repr(C)]
@@ -19,6 +32,65 @@ pub struct DiaResponse {
     _private: [u8; 0],
 }
 
+/// Opaque pointer to Request instance, valid during a handler callback
+#[repr(C)]
+pub struct DiaRequest {
+    _private: [u8; 0],
+}
+
+/// Borrowed bytes handed to C
+///
+/// Points into a request and stays valid until the handler callback returns.
+/// Not NUL-terminated; `ptr` is null when the value is absent.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DiaSlice {
+    /// First byte, or null if absent
+    pub ptr: *const u8,
+    /// Length in bytes
+    pub len: usize,
+}
+
+impl DiaSlice {
+    /// Slice for an absent value
+    const NONE: DiaSlice = DiaSlice {
+        ptr: std::ptr::null(),
+        len: 0,
+    };
+
+    /// Borrow bytes
+    fn new(bytes: &[u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> From<Option<T>> for DiaSlice {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(DiaSlice::NONE, |value| DiaSlice::new(value.as_ref()))
+    }
+}
+
+/// Opaque pointer to BasicController instance
+#[repr(C)]
+pub struct DiaController {
+    _private: [u8; 0],
+}
+
+/// Opaque pointer to a running server
+#[repr(C)]
+pub struct DiaServer {
+    _private: [u8; 0],
+}
+
+/// Opaque pointer to a list of middlewares
+#[repr(C)]
+pub struct DiaMiddleware {
+    _private: [u8; 0],
+}
+
 /// Create a new dia application
 #[unsafe(no_mangle)]
 pub extern "C" fn dia_application_new() -> *mut DiaApplication {
@@ -27,32 +99,36 @@ pub extern "C" fn dia_application_new() -> *mut DiaApplication {
 }
 
 /// Set the host for the application
+///
+/// # Safety
+///
+/// `app` must be null or a live application from `dia_application_new`, and `host` null or a NUL-terminated string.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_application_host(
+pub unsafe extern "C" fn dia_application_host(
     app: *mut DiaApplication, 
     host: *const c_char
 ) -> c_int {
-    if app.is_null() || host.is_null() {
+    if app.is_null() {
         return -1;
     }
 
     unsafe {
-        let _app = app as *mut Application;
-        let _host_str = match CStr::from_ptr(host).to_str() {
-            Ok(s) => s,
-            Err(_) => return -1,
+        let Some(host) = to_str(host) else {
+            return -1;
         };
-
-        // TODO: Implement proper host setting
-        // This is a limitation of the current design
+        (*(app as *mut Application)).set_host(host);
     }
 
     0
 }
 
 /// Set the port for the application
+///
+/// # Safety
+///
+/// `app` must be null or a live application from `dia_application_new`.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_application_port(
+pub unsafe extern "C" fn dia_application_port(
     app: *mut DiaApplication,
     port: u16
 ) -> c_int {
@@ -61,17 +137,19 @@ pub extern "C" fn dia_application_port(
     }
 
     unsafe {
-        let _app = app as *mut Application;
-        // TODO: Implement proper port setting
-        // This is a limitation of the current design
+        (*(app as *mut Application)).set_port(port);
     }
 
     0
 }
 
 /// Run the application (blocking)
+///
+/// # Safety
+///
+/// `app` must be null or a live application from `dia_application_new`. The application is consumed and must not be used afterwards.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_application_run(app: *mut DiaApplication) -> c_int {
+pub unsafe extern "C" fn dia_application_run(app: *mut DiaApplication) -> c_int {
     if app.is_null() {
         return -1;
     }
@@ -92,9 +170,127 @@ pub extern "C" fn dia_application_run(app: *mut DiaApplication) -> c_int {
     }
 }
 
+/// Server started in the background, with the runtime driving it
+struct BackgroundServer {
+    /// Runtime running the server
+    runtime: tokio::runtime::Runtime,
+    /// Server handle
+    server: Server,
+    /// Bound address, as returned by `dia_server_local_addr`
+    local_addr: String,
+}
+
+/// Start the application in the background
+///
+/// Consumes the application, like `dia_application_run`, and returns once the
+/// server listens, or null if it could not start. The server runs until
+/// `dia_server_stop` and must be freed with `dia_server_free`.
+///
+/// # Safety
+///
+/// `app` must be null or a live application from `dia_application_new`. The application is consumed and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_start(app: *mut DiaApplication) -> *mut DiaServer {
+    if app.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    let app = unsafe { Box::from_raw(app as *mut Application) };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    let server = match runtime.block_on(async { app.start() }) {
+        Ok(server) => server,
+        Err(err) => {
+            log::error!("Failed to start server: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let local_addr = server.local_addr().to_string();
+    let server = Box::new(BackgroundServer { runtime, server, local_addr });
+    Box::into_raw(server) as *mut DiaServer
+}
+
+/// Get the address the server listens on, such as `127.0.0.1:8080`
+///
+/// With port 0, this holds the port picked by the system. The slice stays
+/// valid until the server is freed.
+///
+/// # Safety
+///
+/// `server` must be null or a live server from `dia_application_start`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_server_local_addr(server: *const DiaServer) -> DiaSlice {
+    let server = unsafe { (server as *const BackgroundServer).as_ref() };
+    server.map(|server| server.local_addr.as_str()).into()
+}
+
+/// Stop the server, blocking until it has stopped
+///
+/// A graceful stop waits up to `timeout_ms` for requests in progress to
+/// finish before closing the connections; otherwise they are closed right
+/// away. May be called from another thread than `dia_server_wait`.
+///
+/// # Safety
+///
+/// `server` must be null or a live server from `dia_application_start`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_server_stop(server: *mut DiaServer, graceful: bool, timeout_ms: u64) -> c_int {
+    let Some(server) = (unsafe { (server as *const BackgroundServer).as_ref() }) else {
+        return -1;
+    };
+
+    let timeout = Duration::from_millis(timeout_ms);
+    server.runtime.block_on(server.server.stop(graceful, timeout));
+    0
+}
+
+/// Block until the server has stopped
+///
+/// # Safety
+///
+/// `server` must be null or a live server from `dia_application_start`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_server_wait(server: *mut DiaServer) -> c_int {
+    let Some(server) = (unsafe { (server as *const BackgroundServer).as_ref() }) else {
+        return -1;
+    };
+
+    match server.runtime.block_on(server.server.wait()) {
+        Ok(()) => 0,
+        Err(err) => {
+            log::error!("Server failed: {}", err);
+            -1
+        }
+    }
+}
+
+/// Free a server, stopping it first if it is still running
+///
+/// # Safety
+///
+/// `server` must be null or a server from `dia_application_start` not freed yet, and no other
+/// thread may be inside `dia_server_stop` or `dia_server_wait` with it.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_server_free(server: *mut DiaServer) {
+    if !server.is_null() {
+        unsafe {
+            let server = Box::from_raw(server as *mut BackgroundServer);
+            server.runtime.block_on(server.server.stop(false, Duration::ZERO));
+        }
+    }
+}
+
 /// Free the application
+///
+/// # Safety
+///
+/// `app` must be null or an application from `dia_application_new` not freed or consumed
+/// by `dia_application_run` or `dia_application_start` yet.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_application_free(app: *mut DiaApplication) {
+pub unsafe extern "C" fn dia_application_free(app: *mut DiaApplication) {
     if !app.is_null() {
         unsafe {
             drop(Box::from_raw(app as *mut Application));
@@ -110,76 +306,140 @@ pub extern "C" fn dia_response_new() -> *mut DiaResponse {
 }
 
 /// Set response text
+///
+/// # Safety
+///
+/// `resp` must be null or a live response from `dia_response_new` or handed to a middleware callback,
+/// and `text` null or a NUL-terminated string.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_response_text(
+pub unsafe extern "C" fn dia_response_text(
     resp: *mut DiaResponse,
     text: *const c_char
 ) -> c_int {
-    if resp.is_null() || text.is_null() {
+    if resp.is_null() {
         return -1;
     }
 
     unsafe {
-        let _response = resp as *mut Response;
-        let _text_str = match CStr::from_ptr(text).to_str() {
-            Ok(s) => s,
-            Err(_) => return -1,
+        let Some(text) = to_str(text) else {
+            return -1;
         };
-
-        // TODO: Implement proper response text setting
-        // This is a limitation of the current design
+        (*(resp as *mut Response)).set_text(text);
     }
 
     0
 }
 
 /// Set response JSON from string
+///
+/// Returns -1 without touching the response if the string is not valid JSON.
+///
+/// # Safety
+///
+/// `resp` must be null or a live response from `dia_response_new` or handed to a middleware callback,
+/// and `json_str` null or a NUL-terminated string.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_response_json(
+pub unsafe extern "C" fn dia_response_json(
     resp: *mut DiaResponse,
     json_str: *const c_char
 ) -> c_int {
-    if resp.is_null() || json_str.is_null() {
+    if resp.is_null() {
         return -1;
     }
 
     unsafe {
-        let _response = resp as *mut Response;
-        let _json_str = match CStr::from_ptr(json_str).to_str() {
-            Ok(s) => s,
-            Err(_) => return -1,
+        let Some(json) = to_str(json_str).and_then(|json| serde_json::from_str::<Value>(json).ok()) else {
+            return -1;
         };
-
-        // TODO: Implement proper JSON response setting
-        // This is a limitation of the current design
+        (*(resp as *mut Response)).set_json(json);
     }
 
     0
 }
 
 /// Set response status
+///
+/// Returns -1 for codes outside 100-999.
+///
+/// # Safety
+///
+/// `resp` must be null or a live response from `dia_response_new` or handed to a middleware callback.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_response_status(
+pub unsafe extern "C" fn dia_response_status(
     resp: *mut DiaResponse,
     status: u16
+) -> c_int {
+    if resp.is_null() || StatusCode::from_u16(status).is_err() {
+        return -1;
+    }
+
+    unsafe {
+        (*(resp as *mut Response)).set_status(status);
+    }
+
+    0
+}
+
+/// Set a response header, replacing any existing value
+///
+/// # Safety
+///
+/// `resp` must be null or a live response from `dia_response_new` or handed to a middleware callback,
+/// and `name` and `value` null or NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_response_header(
+    resp: *mut DiaResponse,
+    name: *const c_char,
+    value: *const c_char
 ) -> c_int {
     if resp.is_null() {
         return -1;
     }
 
     unsafe {
-        let _response = resp as *mut Response;
-        
-        // TODO: Implement proper status setting
-        // This is a limitation of the current design
+        let (Some(name), Some(value)) = (to_str(name), to_str(value)) else {
+            return -1;
+        };
+        (*(resp as *mut Response)).set_header(name, value);
+    }
+
+    0
+}
+
+/// Add a cookie to the response
+///
+/// # Safety
+///
+/// `resp` must be null or a live response from `dia_response_new` or handed to a middleware callback,
+/// and `name` and `value` null or NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_response_cookie(
+    resp: *mut DiaResponse,
+    name: *const c_char,
+    value: *const c_char
+) -> c_int {
+    if resp.is_null() {
+        return -1;
+    }
+
+    unsafe {
+        let (Some(name), Some(value)) = (to_str(name), to_str(value)) else {
+            return -1;
+        };
+        (*(resp as *mut Response)).add_cookie(Cookie::new(name.to_string(), value.to_string()));
     }
 
     0
 }
 
 /// Free the response
+///
+/// # Safety
+///
+/// `resp` must be null or a response from `dia_response_new` not freed or returned from a
+/// handler callback yet.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_response_free(resp: *mut DiaResponse) {
+pub unsafe extern "C" fn dia_response_free(resp: *mut DiaResponse) {
     if !resp.is_null() {
         unsafe {
             drop(Box::from_raw(resp as *mut Response));
@@ -187,23 +447,1031 @@ pub extern "C" fn dia_response_free(resp: *mut DiaResponse) {
     }
 }
 
-/// Simple handler function type for FFI
-pub type DiaHandlerFn = extern "C" fn() -> *mut DiaResponse;
+/// Route handler callback
+///
+/// Receives the request, valid until the callback returns, and the
+/// `user_data` pointer given at registration. Returns a response from
+/// `dia_response_new`, whose ownership passes back to dia, or null to answer
+/// 500 Internal Server Error. Registering a null callback fails with -1.
+///
+/// Callbacks run on a blocking thread pool, possibly on several threads at
+/// once, so `user_data` must be safe to share between threads.
+pub type DiaHandlerFn = Option<extern "C" fn(req: *const DiaRequest, user_data: *mut c_void) -> *mut DiaResponse>;
+
+/// `user_data` pointer handed back to C callbacks
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+// The C side promises `user_data` may be used from any thread
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    /// Get the raw pointer
+    fn get(self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Wrap a C callback into a route handler, or `None` if the callback is null
+///
+/// The callback blocks, so it is run on actix's blocking thread pool instead
+/// of a worker thread.
+fn callback_handler(handler: DiaHandlerFn, user_data: *mut c_void) -> Option<HandlerFn> {
+    let handler = handler?;
+    let user_data = UserData(user_data);
+    Some(Arc::new(move |req, _resp| {
+        Box::pin(async move {
+            let result = web::block(move || {
+                let resp = handler(&req as *const Request as *const DiaRequest, user_data.get());
+                if resp.is_null() {
+                    return None;
+                }
+                // Ownership of the response returns to Rust here
+                Some(*unsafe { Box::from_raw(resp as *mut Response) })
+            })
+            .await;
+
+            match result {
+                Ok(Some(resp)) => resp,
+                Ok(None) => {
+                    log::error!("Route handler returned no response");
+                    Response::internal_error()
+                }
+                Err(err) => {
+                    log::error!("Route handler failed: {}", err);
+                    Response::internal_error()
+                }
+            }
+        })
+    }))
+}
+
+/// Convert a C string to a `&str`, or `None` if it is null or not UTF-8
+unsafe fn to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_str().ok()
+}
+
+/// Register a route for any HTTP method
+///
+/// `method` is a method name such as `GET` or `OPTIONS`; the path may use
+/// actix-web patterns like `/users/{id}`.
+///
+/// # Safety
+///
+/// `app` must be null or a live application from `dia_application_new`, and `method` and
+/// `path` null or NUL-terminated strings. `user_data` is handed to `handler` as is, so it must
+/// stay valid and usable from any thread for as long as the application runs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_route(
+    app: *mut DiaApplication,
+    method: *const c_char,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    if app.is_null() {
+        return -1;
+    }
+
+    unsafe {
+        let (Some(method), Some(path)) = (to_str(method), to_str(path)) else {
+            return -1;
+        };
+        if Method::from_bytes(method.as_bytes()).is_err() {
+            return -1;
+        }
+        let Some(handler) = callback_handler(handler, user_data) else {
+            return -1;
+        };
+
+        let app = &mut *(app as *mut Application);
+        app.add_route(Route::new(method, path, handler));
+    }
+
+    0
+}
+
+/// Register a GET route
+///
+/// # Safety
+///
+/// Same as `dia_application_route`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_get(
+    app: *mut DiaApplication,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { dia_application_route(app, c"GET".as_ptr(), path, handler, user_data) }
+}
+
+/// Register a POST route
+///
+/// # Safety
+///
+/// Same as `dia_application_route`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_post(
+    app: *mut DiaApplication,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { dia_application_route(app, c"POST".as_ptr(), path, handler, user_data) }
+}
+
+/// Register a PUT route
+///
+/// # Safety
+///
+/// Same as `dia_application_route`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_put(
+    app: *mut DiaApplication,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { dia_application_route(app, c"PUT".as_ptr(), path, handler, user_data) }
+}
+
+/// Register a DELETE route
+///
+/// # Safety
+///
+/// Same as `dia_application_route`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_delete(
+    app: *mut DiaApplication,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { dia_application_route(app, c"DELETE".as_ptr(), path, handler, user_data) }
+}
 
-/// Register a simple GET route
+/// Register a PATCH route
+///
+/// # Safety
+///
+/// Same as `dia_application_route`.
 #[unsafe(no_mangle)]
-pub extern "C" fn dia_application_get(
+pub unsafe extern "C" fn dia_application_patch(
     app: *mut DiaApplication,
     path: *const c_char,
-    _handler: DiaHandlerFn
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { dia_application_route(app, c"PATCH".as_ptr(), path, handler, user_data) }
+}
+
+/// Add a controller's routes to the application
+///
+/// The routes are copied: the controller stays owned by the caller and must
+/// still be freed with `dia_controller_free`.
+///
+/// # Safety
+///
+/// `app` must be null or a live application from `dia_application_new`, and
+/// `controller` null or a live controller from `dia_controller_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_controller(
+    app: *mut DiaApplication,
+    controller: *const DiaController
+) -> c_int {
+    if app.is_null() || controller.is_null() {
+        return -1;
+    }
+
+    unsafe {
+        let app = &mut *(app as *mut Application);
+        let controller = &*(controller as *const BasicController);
+        app.add_controller(controller.clone());
+    }
+
+    0
+}
+
+/// Create a new controller
+#[unsafe(no_mangle)]
+pub extern "C" fn dia_controller_new() -> *mut DiaController {
+    let controller = Box::new(BasicController::new());
+    Box::into_raw(controller) as *mut DiaController
+}
+
+/// Register a controller route for any HTTP method
+///
+/// Takes the same pointers as `dia_controller_get`.
+unsafe fn controller_route(
+    ctrl: *mut DiaController,
+    method: &str,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    if ctrl.is_null() {
+        return -1;
+    }
+
+    unsafe {
+        let (Some(path), Some(handler)) = (to_str(path), callback_handler(handler, user_data)) else {
+            return -1;
+        };
+        let controller = &mut *(ctrl as *mut BasicController);
+        controller.add_route(Route::new(method, path, handler));
+    }
+
+    0
+}
+
+/// Add a GET route to a controller
+///
+/// # Safety
+///
+/// `ctrl` must be null or a live controller from `dia_controller_new`, and `path` null or a
+/// NUL-terminated string. `user_data` is handed to `handler` as is, so it must stay valid and
+/// usable from any thread for as long as the routes are served.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_controller_get(
+    ctrl: *mut DiaController,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { controller_route(ctrl, "GET", path, handler, user_data) }
+}
+
+/// Add a POST route to a controller
+///
+/// # Safety
+///
+/// Same as `dia_controller_get`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_controller_post(
+    ctrl: *mut DiaController,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { controller_route(ctrl, "POST", path, handler, user_data) }
+}
+
+/// Add a PUT route to a controller
+///
+/// # Safety
+///
+/// Same as `dia_controller_get`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_controller_put(
+    ctrl: *mut DiaController,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { controller_route(ctrl, "PUT", path, handler, user_data) }
+}
+
+/// Add a DELETE route to a controller
+///
+/// # Safety
+///
+/// Same as `dia_controller_get`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_controller_delete(
+    ctrl: *mut DiaController,
+    path: *const c_char,
+    handler: DiaHandlerFn,
+    user_data: *mut c_void
+) -> c_int {
+    unsafe { controller_route(ctrl, "DELETE", path, handler, user_data) }
+}
+
+/// Add a middleware callback around a controller's routes
+///
+/// # Safety
+///
+/// `ctrl` must be null or a live controller from `dia_controller_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_controller_middleware(
+    ctrl: *mut DiaController,
+    handler: DiaMiddlewareFn
+) -> c_int {
+    let Some(handler) = handler else {
+        return -1;
+    };
+    if ctrl.is_null() {
+        return -1;
+    }
+
+    unsafe {
+        let controller = &mut *(ctrl as *mut BasicController);
+        controller.add_middleware(CallbackMiddleware { handler });
+    }
+
+    0
+}
+
+/// Free a controller
+///
+/// # Safety
+///
+/// `ctrl` must be null or a controller from `dia_controller_new` not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_controller_free(ctrl: *mut DiaController) {
+    if !ctrl.is_null() {
+        unsafe {
+            let _ = Box::from_raw(ctrl as *mut BasicController);
+        }
+    }
+}
+
+/// Middleware callback
+///
+/// Receives the request and a fresh response, both valid until the callback
+/// returns. Returning 0 lets the request continue, and whatever the callback
+/// set on the response is applied to the final one: headers (including
+/// cookies) are added, a status other than 200 replaces the handler's, and a
+/// body replaces the handler's body. Any other value stops the request and
+/// answers with the response as set, for example a 401 from an
+/// authentication check. Registering a null callback fails with -1.
+///
+/// Callbacks run on a blocking thread pool, like route handlers, so they may
+/// block, possibly on several threads at once.
+pub type DiaMiddlewareFn = Option<extern "C" fn(req: *const DiaRequest, resp: *mut DiaResponse) -> c_int>;
+
+/// Middleware running a C callback before the handler
+struct CallbackMiddleware {
+    /// C callback
+    handler: extern "C" fn(req: *const DiaRequest, resp: *mut DiaResponse) -> c_int,
+}
+
+/// Responses set by callbacks that let the request continue
+///
+/// Every callback that continues pushes one entry, and `after_request` hooks
+/// run in reverse order, so each pops its own. The stack is shared with the
+/// request clones handed to controller middlewares.
+#[derive(Default)]
+struct CallbackResponses(Mutex<Vec<Response>>);
+
+impl CallbackResponses {
+    /// Push the response of a callback that let the request continue
+    fn push(req: &mut Request, resp: Response) {
+        if !req.extensions().contains::<CallbackResponses>() {
+            req.extensions_mut().insert(CallbackResponses::default());
+        }
+        if let Some(pending) = req.extensions().get::<CallbackResponses>() {
+            pending.0.lock().unwrap_or_else(|err| err.into_inner()).push(resp);
+        }
+    }
+
+    /// Pop the response of the innermost callback
+    fn pop(req: &Request) -> Option<Response> {
+        let pending = req.extensions().get::<CallbackResponses>()?;
+        pending.0.lock().unwrap_or_else(|err| err.into_inner()).pop()
+    }
+}
+
+impl Middleware for CallbackMiddleware {
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        let handler = self.handler;
+        let request = req.clone();
+        Box::pin(async move {
+            let result = web::block(move || {
+                let mut resp = Response::new();
+                let result = handler(
+                    &request as *const Request as *const DiaRequest,
+                    &mut resp as *mut Response as *mut DiaResponse,
+                );
+                (result, resp)
+            })
+            .await;
+
+            match result {
+                Ok((0, resp)) => {
+                    CallbackResponses::push(req, resp);
+                    None
+                }
+                Ok((_, resp)) => Some(resp),
+                Err(err) => {
+                    log::error!("Middleware callback failed: {}", err);
+                    Some(Response::internal_error())
+                }
+            }
+        })
+    }
+
+    fn after_request<'a>(
+        &'a self,
+        req: &'a Request,
+        mut resp: Response,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
+        if let Some(mut pending) = CallbackResponses::pop(req) {
+            let mut replaced: Vec<&str> = Vec::new();
+            for (name, value) in pending.get_headers().iter() {
+                // The callback's headers replace the handler's, except cookies
+                if name.eq_ignore_ascii_case("set-cookie") || replaced.contains(&name) {
+                    resp = resp.append_header(name, value);
+                } else {
+                    resp.set_header(name, value);
+                    replaced.push(name);
+                }
+            }
+            if pending.get_status() != StatusCode::OK.as_u16() {
+                resp.set_status(pending.get_status());
+            }
+            if !matches!(pending.get_body(), ResponseBody::Empty) {
+                resp.replace_body(pending.replace_body(ResponseBody::Empty));
+            }
+        }
+        Box::pin(async move { resp })
+    }
+
+    fn name(&self) -> &str {
+        "Callback"
+    }
+}
+
+/// Create a new, empty middleware list
+#[unsafe(no_mangle)]
+pub extern "C" fn dia_middleware_new() -> *mut DiaMiddleware {
+    let middlewares: Box<Vec<Arc<dyn Middleware>>> = Box::default();
+    Box::into_raw(middlewares) as *mut DiaMiddleware
+}
+
+/// Add a middleware to a middleware list
+///
+/// `mw` must be null or a live middleware list from `dia_middleware_new`.
+unsafe fn push_middleware<M: Middleware + 'static>(mw: *mut DiaMiddleware, middleware: M) -> c_int {
+    if mw.is_null() {
+        return -1;
+    }
+
+    unsafe {
+        let middlewares = &mut *(mw as *mut Vec<Arc<dyn Middleware>>);
+        middlewares.push(Arc::new(middleware));
+    }
+
+    0
+}
+
+/// Add CORS handling allowing any origin
+///
+/// # Safety
+///
+/// `mw` must be null or a live middleware list from `dia_middleware_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_middleware_cors(mw: *mut DiaMiddleware) -> c_int {
+    unsafe { push_middleware(mw, CorsMiddleware::new()) }
+}
+
+/// Add request logging
+///
+/// # Safety
+///
+/// `mw` must be null or a live middleware list from `dia_middleware_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_middleware_logger(mw: *mut DiaMiddleware) -> c_int {
+    unsafe { push_middleware(mw, LoggingMiddleware::new()) }
+}
+
+/// Add a middleware callback
+///
+/// # Safety
+///
+/// `mw` must be null or a live middleware list from `dia_middleware_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_middleware_custom(
+    mw: *mut DiaMiddleware,
+    handler: DiaMiddlewareFn
+) -> c_int {
+    let Some(handler) = handler else {
+        return -1;
+    };
+    unsafe { push_middleware(mw, CallbackMiddleware { handler }) }
+}
+
+/// Free a middleware list
+///
+/// # Safety
+///
+/// `mw` must be null or a middleware list from `dia_middleware_new` not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_middleware_free(mw: *mut DiaMiddleware) {
+    if !mw.is_null() {
+        unsafe {
+            let _ = Box::from_raw(mw as *mut Vec<Arc<dyn Middleware>>);
+        }
+    }
+}
+
+/// Add a middleware list to the application, in order
+///
+/// The middlewares are shared: the list stays owned by the caller and must
+/// still be freed with `dia_middleware_free`.
+///
+/// # Safety
+///
+/// `app` must be null or a live application from `dia_application_new`, and `mw` null or
+/// a live middleware list from `dia_middleware_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_application_middleware(
+    app: *mut DiaApplication,
+    mw: *const DiaMiddleware
+) -> c_int {
+    if app.is_null() || mw.is_null() {
+        return -1;
+    }
+
+    unsafe {
+        let app = &mut *(app as *mut Application);
+        let middlewares = &*(mw as *const Vec<Arc<dyn Middleware>>);
+        for middleware in middlewares {
+            app.add_middleware(middleware.clone());
+        }
+    }
+
+    0
+}
+
+/// Borrow the request behind a handle
+unsafe fn request<'a>(req: *const DiaRequest) -> Option<&'a Request> {
+    unsafe { (req as *const Request).as_ref() }
+}
+
+/// Get the request method
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_method(req: *const DiaRequest) -> DiaSlice {
+    unsafe { request(req) }.map(Request::method).into()
+}
+
+/// Get the request path, without the query string
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_path(req: *const DiaRequest) -> DiaSlice {
+    unsafe { request(req) }.map(Request::path).into()
+}
+
+/// Get the raw query string
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_query_string(req: *const DiaRequest) -> DiaSlice {
+    unsafe { request(req) }.map(Request::query_string).into()
+}
+
+/// Get the first value of a query parameter
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback, and `name` null or a
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_query(req: *const DiaRequest, name: *const c_char) -> DiaSlice {
+    unsafe { request(req).zip(to_str(name)) }
+        .and_then(|(req, name)| req.query(name))
+        .into()
+}
+
+/// Get a path parameter
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback, and `name` null or a
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_param(req: *const DiaRequest, name: *const c_char) -> DiaSlice {
+    unsafe { request(req).zip(to_str(name)) }
+        .and_then(|(req, name)| req.param(name))
+        .into()
+}
+
+/// Get the number of path parameters
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_param_count(req: *const DiaRequest) -> usize {
+    unsafe { request(req) }.map_or(0, |req| req.path_params().len())
+}
+
+/// Get the path parameter at `index`, in no particular order
+///
+/// Returns -1 if the index is out of range.
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback, and `name` and
+/// `value` each null or valid for writing a `DiaSlice`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_param_at(
+    req: *const DiaRequest,
+    index: usize,
+    name: *mut DiaSlice,
+    value: *mut DiaSlice
 ) -> c_int {
-    if app.is_null() || path.is_null() {
+    let Some(req) = (unsafe { request(req) }) else {
         return -1;
+    };
+    match req.path_params().iter().nth(index) {
+        Some((param, param_value)) => unsafe { write_pair(name, value, param.as_bytes(), param_value.as_bytes()) },
+        None => -1,
     }
+}
+
+/// Get the first value of a header, matched case-insensitively
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback, and `name` null or a
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_header(req: *const DiaRequest, name: *const c_char) -> DiaSlice {
+    unsafe { request(req).zip(to_str(name)) }
+        .and_then(|(req, name)| req.header_bytes(name))
+        .into()
+}
 
-    // TODO: Implement route registration
-    // This requires a more complex design to handle the conversion
-    // between C function pointers and Rust async functions
-    
+/// Get the number of header values
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_header_count(req: *const DiaRequest) -> usize {
+    unsafe { request(req) }.map_or(0, |req| req.headers().iter().count())
+}
+
+/// Get the header at `index`; repeated headers appear once per value
+///
+/// Returns -1 if the index is out of range.
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback, and `name` and
+/// `value` each null or valid for writing a `DiaSlice`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_header_at(
+    req: *const DiaRequest,
+    index: usize,
+    name: *mut DiaSlice,
+    value: *mut DiaSlice
+) -> c_int {
+    let Some(req) = (unsafe { request(req) }) else {
+        return -1;
+    };
+    match req.headers().iter().nth(index) {
+        Some((header, header_value)) => unsafe { write_pair(name, value, header.as_bytes(), header_value) },
+        None => -1,
+    }
+}
+
+/// Get the client IP address
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_remote_ip(req: *const DiaRequest) -> DiaSlice {
+    unsafe { request(req) }.and_then(Request::remote_ip).into()
+}
+
+/// Get the request body bytes
+///
+/// # Safety
+///
+/// `req` must be null or the request handed to the running callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dia_request_get_body(req: *const DiaRequest) -> DiaSlice {
+    unsafe { request(req) }.map(Request::body).into()
+}
+
+/// Write a name and value through optional out pointers
+unsafe fn write_pair(name: *mut DiaSlice, value: *mut DiaSlice, name_bytes: &[u8], value_bytes: &[u8]) -> c_int {
+    unsafe {
+        if let Some(name) = name.as_mut() {
+            *name = DiaSlice::new(name_bytes);
+        }
+        if let Some(value) = value.as_mut() {
+            *value = DiaSlice::new(value_bytes);
+        }
+    }
     0
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::MiddlewareChain;
+    use crate::Controller;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::App;
+    use serde_json::json;
+    use std::io::{Read, Write};
+
+    /// Borrow the response behind a handle
+    fn response<'a>(resp: *mut DiaResponse) -> &'a Response {
+        unsafe { &*(resp as *const Response) }
+    }
+
+    #[test]
+    fn application_host_and_port() {
+        let app = dia_application_new();
+        unsafe {
+            assert_eq!(dia_application_host(app, c"0.0.0.0".as_ptr()), 0);
+            assert_eq!(dia_application_port(app, 3000), 0);
+            assert_eq!(dia_application_host(app, std::ptr::null()), -1);
+            assert_eq!(dia_application_port(std::ptr::null_mut(), 3000), -1);
+        }
+
+        let application = unsafe { &*(app as *const Application) };
+        assert_eq!(application.get_host(), "0.0.0.0");
+        assert_eq!(application.get_port(), 3000);
+        unsafe { dia_application_free(app) };
+    }
+
+    #[test]
+    fn response_status_and_body() {
+        let resp = dia_response_new();
+        unsafe {
+            assert_eq!(dia_response_status(resp, 201), 0);
+            assert_eq!(dia_response_status(resp, 1000), -1);
+        }
+        assert_eq!(response(resp).get_status(), 201);
+
+        assert_eq!(unsafe { dia_response_text(resp, c"hello".as_ptr()) }, 0);
+        assert!(matches!(response(resp).get_body(), ResponseBody::Text(text) if text == "hello"));
+        assert_eq!(response(resp).get_header("content-type"), Some("text/plain; charset=utf-8"));
+
+        assert_eq!(unsafe { dia_response_json(resp, c"{\"id\": 1}".as_ptr()) }, 0);
+        assert!(matches!(response(resp).get_body(), ResponseBody::Json(value) if *value == json!({"id": 1})));
+        assert_eq!(unsafe { dia_response_json(resp, c"{not json".as_ptr()) }, -1);
+        assert!(matches!(response(resp).get_body(), ResponseBody::Json(_)));
+        assert_eq!(response(resp).get_header("content-type"), Some("application/json"));
+
+        assert_eq!(unsafe { dia_response_header(resp, c"X-Id".as_ptr(), c"42".as_ptr()) }, 0);
+        assert_eq!(response(resp).get_header("x-id"), Some("42"));
+        unsafe { dia_response_free(resp) };
+    }
+
+    extern "C" fn echo_path(req: *const DiaRequest, user_data: *mut c_void) -> *mut DiaResponse {
+        unsafe {
+            let path = dia_request_get_path(req);
+            let path = std::slice::from_raw_parts(path.ptr, path.len);
+            let resp = dia_response_new();
+            let prefix = CStr::from_ptr(user_data as *const c_char).to_str().unwrap();
+            let text = std::ffi::CString::new(format!("{}{}", prefix, String::from_utf8_lossy(path))).unwrap();
+            dia_response_text(resp, text.as_ptr());
+            dia_response_status(resp, 202);
+            resp
+        }
+    }
+
+    extern "C" fn no_response(_req: *const DiaRequest, _user_data: *mut c_void) -> *mut DiaResponse {
+        std::ptr::null_mut()
+    }
+
+    /// Copy a slice handed to C, or "null" if absent
+    fn slice_text(slice: DiaSlice) -> String {
+        if slice.ptr.is_null() {
+            return "null".to_string();
+        }
+        String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(slice.ptr, slice.len) }).into_owned()
+    }
+
+    extern "C" fn describe_request(req: *const DiaRequest, _user_data: *mut c_void) -> *mut DiaResponse {
+        let mut lines = Vec::new();
+        unsafe {
+            lines.push(format!("query_string={}", slice_text(dia_request_get_query_string(req))));
+            lines.push(format!("query tag={}", slice_text(dia_request_get_query(req, c"tag".as_ptr()))));
+            lines.push(format!("query page={}", slice_text(dia_request_get_query(req, c"page".as_ptr()))));
+            lines.push(format!("param id={}", slice_text(dia_request_get_param(req, c"id".as_ptr()))));
+            lines.push(format!("param name={}", slice_text(dia_request_get_param(req, c"name".as_ptr()))));
+
+            let params = dia_request_param_count(req);
+            for index in 0..params {
+                let (mut name, mut value) = (DiaSlice::NONE, DiaSlice::NONE);
+                assert_eq!(dia_request_param_at(req, index, &mut name, &mut value), 0);
+                lines.push(format!("param_at {}={}", slice_text(name), slice_text(value)));
+            }
+            let past = dia_request_param_at(req, params, std::ptr::null_mut(), std::ptr::null_mut());
+            lines.push(format!("param_at past end={}", past));
+
+            // Header order is unspecified, so only the repeated one is listed
+            let headers = dia_request_header_count(req);
+            for index in 0..headers {
+                let (mut name, mut value) = (DiaSlice::NONE, DiaSlice::NONE);
+                assert_eq!(dia_request_header_at(req, index, &mut name, &mut value), 0);
+                if slice_text(name) == "x-tag" {
+                    lines.push(format!("header_at x-tag={}", slice_text(value)));
+                }
+            }
+            let past = dia_request_header_at(req, headers, std::ptr::null_mut(), std::ptr::null_mut());
+            lines.push(format!("header_at past end={}", past));
+
+            lines.push(format!("remote_ip={}", slice_text(dia_request_get_remote_ip(req))));
+            lines.push(format!("body={}", slice_text(dia_request_get_body(req))));
+
+            let resp = dia_response_new();
+            let text = std::ffi::CString::new(lines.join("\n")).unwrap();
+            dia_response_text(resp, text.as_ptr());
+            resp
+        }
+    }
+
+    #[actix_web::test]
+    async fn callbacks_read_the_request() {
+        let describe = callback_handler(Some(describe_request), std::ptr::null_mut()).unwrap();
+        let controller = BasicController::new().route(Route::new("POST", "/users/{id}", describe));
+        let app = init_service(App::new().configure(|cfg| controller.register_routes(cfg))).await;
+
+        let req = TestRequest::post()
+            .uri("/users/42?tag=a&tag=b")
+            .peer_addr("10.0.0.1:4321".parse().unwrap())
+            .append_header(("X-Tag", "a"))
+            .append_header(("X-Tag", "b"))
+            .set_payload("hello")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body = read_body(resp).await;
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap().lines().collect::<Vec<_>>(),
+            [
+                "query_string=tag=a&tag=b",
+                "query tag=a",
+                "query page=null",
+                "param id=42",
+                "param name=null",
+                "param_at id=42",
+                "param_at past end=-1",
+                "header_at x-tag=a",
+                "header_at x-tag=b",
+                "header_at past end=-1",
+                "remote_ip=10.0.0.1",
+                "body=hello",
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn callbacks_handle_requests() {
+        let echo = callback_handler(Some(echo_path), c"path=".as_ptr() as *mut c_void).unwrap();
+        let none = callback_handler(Some(no_response), std::ptr::null_mut()).unwrap();
+        let controller = BasicController::new()
+            .route(Route::new("GET", "/echo", echo))
+            .route(Route::new("GET", "/none", none));
+        let app = init_service(App::new().configure(|cfg| controller.register_routes(cfg))).await;
+
+        let resp = call_service(&app, TestRequest::get().uri("/echo").to_request()).await;
+        assert_eq!(resp.status(), 202);
+        assert_eq!(read_body(resp).await, "path=/echo");
+
+        let resp = call_service(&app, TestRequest::get().uri("/none").to_request()).await;
+        assert_eq!(resp.status(), 500);
+    }
+
+    #[test]
+    fn null_callbacks_are_rejected() {
+        let app = dia_application_new();
+        let ctrl = dia_controller_new();
+        let mw = dia_middleware_new();
+        unsafe {
+            assert_eq!(dia_application_get(app, c"/".as_ptr(), None, std::ptr::null_mut()), -1);
+            assert_eq!(dia_application_route(app, c"GET".as_ptr(), c"/".as_ptr(), None, std::ptr::null_mut()), -1);
+            assert_eq!(dia_controller_get(ctrl, c"/".as_ptr(), None, std::ptr::null_mut()), -1);
+            assert_eq!(dia_controller_middleware(ctrl, None), -1);
+            assert_eq!(dia_middleware_custom(mw, None), -1);
+
+            assert!((*(mw as *const Vec<Arc<dyn Middleware>>)).is_empty());
+            dia_middleware_free(mw);
+            dia_controller_free(ctrl);
+            dia_application_free(app);
+        }
+    }
+
+    extern "C" fn require_token(req: *const DiaRequest, resp: *mut DiaResponse) -> c_int {
+        unsafe {
+            dia_response_header(resp, c"X-Checked".as_ptr(), c"yes".as_ptr());
+            if dia_request_get_header(req, c"x-token".as_ptr()).ptr.is_null() {
+                dia_response_status(resp, 401);
+                dia_response_text(resp, c"Unauthorized".as_ptr());
+                return -1;
+            }
+        }
+        0
+    }
+
+    #[actix_web::test]
+    async fn controller_middleware_callbacks() {
+        let ctrl = dia_controller_new();
+        let prefix = c"".as_ptr() as *mut c_void;
+        unsafe {
+            assert_eq!(dia_controller_get(ctrl, c"/private".as_ptr(), Some(echo_path), prefix), 0);
+            assert_eq!(dia_controller_middleware(ctrl, Some(require_token)), 0);
+            assert_eq!(dia_controller_get(std::ptr::null_mut(), c"/x".as_ptr(), Some(echo_path), prefix), -1);
+        }
+
+        let controller = unsafe { &*(ctrl as *const BasicController) }.clone();
+        unsafe { dia_controller_free(ctrl) };
+        let app = init_service(App::new().configure(|cfg| controller.register_routes(cfg))).await;
+
+        let resp = call_service(&app, TestRequest::get().uri("/private").to_request()).await;
+        assert_eq!(resp.status(), 401);
+        assert_eq!(resp.headers().get("x-checked").unwrap(), "yes");
+        assert_eq!(read_body(resp).await, "Unauthorized");
+
+        let req = TestRequest::get().uri("/private").insert_header(("X-Token", "t")).to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+        assert_eq!(resp.headers().get("x-checked").unwrap(), "yes");
+        assert_eq!(read_body(resp).await, "/private");
+    }
+
+    static CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    extern "C" fn rewrite(_req: *const DiaRequest, resp: *mut DiaResponse) -> c_int {
+        let call = CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        let call = std::ffi::CString::new(call.to_string()).unwrap();
+        unsafe {
+            dia_response_cookie(resp, c"call".as_ptr(), call.as_ptr());
+            dia_response_status(resp, 203);
+            dia_response_text(resp, c"rewritten".as_ptr());
+        }
+        0
+    }
+
+    #[actix_web::test]
+    async fn continuing_callbacks_rewrite_the_response() {
+        let middleware: Arc<dyn Middleware> = Arc::new(CallbackMiddleware { handler: rewrite });
+        let chain = MiddlewareChain::new(vec![Box::new(middleware.clone())]);
+        let mut controller = BasicController::new();
+        controller.add_middleware(middleware);
+        controller.add_route(Route::new(
+            "GET",
+            "/echo",
+            callback_handler(Some(echo_path), c"".as_ptr() as *mut c_void).unwrap(),
+        ));
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(chain))
+                .configure(|cfg| controller.register_routes(cfg)),
+        )
+        .await;
+
+        let resp = call_service(&app, TestRequest::get().uri("/echo").to_request()).await;
+        assert_eq!(resp.status(), 203);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "text/plain; charset=utf-8");
+        // The same middleware runs twice, and each run keeps its own cookie
+        let mut cookies: Vec<_> = resp.headers().get_all("set-cookie").map(|value| value.to_str().unwrap()).collect();
+        cookies.sort();
+        assert_eq!(cookies, ["call=1", "call=2"]);
+        assert_eq!(read_body(resp).await, "rewritten");
+    }
+
+    #[test]
+    fn server_starts_and_stops() {
+        let app = dia_application_new();
+        let server = unsafe {
+            dia_application_port(app, 0);
+            dia_application_get(app, c"/echo".as_ptr(), Some(echo_path), c"".as_ptr() as *mut c_void);
+            dia_application_start(app)
+        };
+        assert!(!server.is_null());
+
+        let addr = unsafe { dia_server_local_addr(server) };
+        let addr = std::str::from_utf8(unsafe { std::slice::from_raw_parts(addr.ptr, addr.len) }).unwrap();
+        assert!(!addr.ends_with(":0"));
+
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /echo HTTP/1.1\r\nHost: dia\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 202"));
+        assert!(response.ends_with("/echo"));
+
+        unsafe {
+            assert_eq!(dia_server_stop(server, true, 1000), 0);
+            assert_eq!(dia_server_wait(server), 0);
+        }
+        assert!(std::net::TcpStream::connect(addr).is_err());
+        unsafe { dia_server_free(server) };
+    }
+}
@@ -0,0 +1,759 @@
+//! Files module for dia framework
+//!
+//! Provides file response bodies with conditional and range request support,
+//! the StaticFiles controller for serving a directory and the SpaFiles
+//! controller for hosting single-page app builds.
+
+use crate::controller::{dispatch, BasicController, Controller, HandlerFn, Route};
+use crate::{Request, Response};
+use actix_web::web::{self, Bytes};
+use actix_web::{guard, HttpRequest};
+use futures::Stream;
+use std::io::{self, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Size of the chunks read from disk while streaming a file
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// A byte range of a file on disk, streamed as a response body
+#[derive(Debug, Clone)]
+pub struct FileBody {
+    /// File location
+    path: PathBuf,
+    /// First byte to send
+    offset: u64,
+    /// Number of bytes to send
+    len: u64,
+    /// Full size of the file
+    size: u64,
+}
+
+impl FileBody {
+    /// Path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Offset of the first byte sent
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Number of bytes sent
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Check if no bytes are sent
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Full size of the file on disk
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Restrict the body to `len` bytes starting at `start`
+    pub(crate) fn set_range(&mut self, start: u64, len: u64) {
+        self.offset = start;
+        self.len = len;
+    }
+
+    /// Stream the selected bytes from disk
+    pub(crate) fn into_stream(self) -> impl Stream<Item = io::Result<Bytes>> {
+        futures::stream::try_unfold((None, self), |(file, body)| async move {
+            if body.len == 0 {
+                return Ok(None);
+            }
+            let mut file = match file {
+                Some(file) => file,
+                None => {
+                    let mut file = tokio::fs::File::open(&body.path).await?;
+                    file.seek(SeekFrom::Start(body.offset)).await?;
+                    file
+                }
+            };
+
+            let mut buf = vec![0; body.len.min(CHUNK_SIZE) as usize];
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while streaming"));
+            }
+            buf.truncate(read);
+
+            let rest = FileBody {
+                offset: body.offset + read as u64,
+                len: body.len - read as u64,
+                ..body
+            };
+            Ok(Some((Bytes::from(buf), (Some(file), rest))))
+        })
+    }
+}
+
+/// File body with the validators used for conditional requests
+pub(crate) struct OpenedFile {
+    pub body: FileBody,
+    pub content_type: String,
+    pub etag: String,
+    pub last_modified: Option<String>,
+}
+
+/// Read the metadata of a regular file
+pub(crate) fn open(path: &Path) -> io::Result<OpenedFile> {
+    let meta = std::fs::metadata(path)?;
+    if !meta.is_file() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "not a regular file"));
+    }
+
+    let size = meta.len();
+    let modified = meta.modified().ok();
+    let mtime = modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+
+    Ok(OpenedFile {
+        body: FileBody {
+            path: path.to_path_buf(),
+            offset: 0,
+            len: size,
+            size,
+        },
+        content_type: content_type(path),
+        etag: format!("\"{:x}-{:x}\"", mtime, size),
+        last_modified: modified.map(httpdate::fmt_http_date),
+    })
+}
+
+/// Guess the content type of a file from its extension
+pub(crate) fn content_type(path: &Path) -> String {
+    mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string()
+}
+
+/// Quality an `Accept-Encoding` header gives a content coding
+///
+/// An explicit entry for the coding wins over `*`; unlisted codings get 0.
+pub(crate) fn encoding_quality(header: &str, coding: &str) -> f32 {
+    let mut wildcard = None;
+    for item in header.split(',') {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(coding) {
+            return quality;
+        }
+        if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+    wildcard.unwrap_or(0.0)
+}
+
+/// Outcome of evaluating a `Range` header
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ByteRange {
+    /// Serve `len` bytes starting at `start`
+    Partial { start: u64, len: u64 },
+    /// No byte of the range exists in the file
+    Unsatisfiable,
+    /// Serve the whole file (unsupported unit or multiple ranges)
+    Full,
+}
+
+/// Parse a single-range `Range: bytes=...` header against a file size
+pub(crate) fn parse_range(header: &str, size: u64) -> ByteRange {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(suffix) => (size.saturating_sub(suffix), size.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, size.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+            _ => return ByteRange::Full,
+        },
+    };
+
+    if size == 0 || start >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial {
+        start,
+        len: end - start + 1,
+    }
+}
+
+/// Check an `If-None-Match` header against an entity tag (weak comparison)
+pub(crate) fn etag_matches(header: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    header.trim() == "*" || header.split(',').any(|tag| opaque(tag) == opaque(etag))
+}
+
+/// Check whether a resource is unmodified since an HTTP date
+pub(crate) fn not_modified_since(header: &str, last_modified: &str) -> bool {
+    match (httpdate::parse_http_date(header), httpdate::parse_http_date(last_modified)) {
+        (Ok(since), Ok(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// Check an `If-Range` header, which must match exactly for the range to apply
+pub(crate) fn if_range_matches(header: &str, etag: Option<&str>, last_modified: Option<&str>) -> bool {
+    let header = header.trim();
+    if header.starts_with('"') {
+        // Strong comparison: weak tags never match
+        etag.is_some_and(|etag| !etag.starts_with("W/") && etag == header)
+    } else {
+        let parsed = |date: &str| httpdate::parse_http_date(date).ok();
+        last_modified.is_some_and(|modified| parsed(header).is_some() && parsed(header) == parsed(modified))
+    }
+}
+
+/// Resolve a request path below a root directory
+///
+/// Rejects `..` components and any path that escapes the root after
+/// following symlinks. Directories resolve to their index file.
+pub(crate) fn resolve(root: &Path, tail: &str, index: Option<&str>) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for segment in tail.split('/') {
+        if segment.contains('\\') {
+            return None;
+        }
+        match Path::new(segment).components().next() {
+            None | Some(Component::CurDir) => continue,
+            Some(Component::Normal(part)) if Path::new(segment).components().count() == 1 => path.push(part),
+            _ => return None,
+        }
+    }
+
+    let mut path = within(root, &path)?;
+    if path.is_dir() {
+        path = within(root, &path.join(index?))?;
+    }
+    path.is_file().then_some(path)
+}
+
+/// Canonicalize a path, keeping it only if it lies below the root
+fn within(root: &Path, path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    path.starts_with(root.canonicalize().ok()?).then_some(path)
+}
+
+/// Controller serving the files of a directory
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use dia_core::Application;
+/// use dia_core::files::StaticFiles;
+///
+/// let app = Application::new()
+///     .controller(StaticFiles::new("/static", "./public"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    /// URL prefix the directory is mounted at
+    mount_path: String,
+    /// Directory to serve
+    root: PathBuf,
+    /// File served for directory requests
+    index_file: Option<String>,
+}
+
+impl StaticFiles {
+    /// Serve `root` under the `mount_path` URL prefix
+    pub fn new<M: Into<String>, P: Into<PathBuf>>(mount_path: M, root: P) -> Self {
+        Self {
+            mount_path: mount_path.into().trim_end_matches('/').to_string(),
+            root: root.into(),
+            index_file: Some("index.html".to_string()),
+        }
+    }
+
+    /// Set the file served for directory requests, or `None` to disable
+    pub fn index_file(mut self, name: Option<&str>) -> Self {
+        self.index_file = name.map(str::to_string);
+        self
+    }
+}
+
+impl Controller for StaticFiles {
+    fn register_routes(&self, config: &mut web::ServiceConfig) {
+        let files = Arc::new(self.clone());
+        let handler: HandlerFn = Arc::new(move |req: Request, resp: Response| {
+            let files = files.clone();
+            Box::pin(async move {
+                let tail = req.param("tail").cloned().unwrap_or_default();
+                blocking(move || match resolve(&files.root, &tail, files.index_file.as_deref()) {
+                    Some(path) => resp.file(path),
+                    None => Response::not_found(),
+                })
+                .await
+            })
+        });
+
+        BasicController::new()
+            .base_path(self.mount_path.clone())
+            .route(Route::get("/{tail:.*}", handler.clone()))
+            .route(Route::new("HEAD", "/{tail:.*}", handler))
+            .register_routes(config);
+    }
+
+    fn base_path(&self) -> Option<&str> {
+        Some(&self.mount_path)
+    }
+}
+
+/// Precompressed siblings, preferred in this order at equal quality
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// Controller hosting a single-page app build directory
+///
+/// Existing files are served as with `StaticFiles`. Any other GET or HEAD
+/// request outside the excluded prefixes (`/api` by default) gets the index
+/// file so client-side routing can handle it; missing paths with a file
+/// extension still return 404 so broken asset links are not masked.
+///
+/// Hashed assets (such as `index-4f3a9c1b.js` or `main.3f2a1b4c.css`) are
+/// cached for a year, the index file is sent with `Cache-Control: no-cache`,
+/// and the `.br`/`.gz` sibling the client prefers in `Accept-Encoding` is
+/// served when there is one.
+///
+/// The fallback matches every GET and HEAD request that is not excluded, so
+/// register this controller after the API controllers.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use dia_core::Application;
+/// use dia_core::files::SpaFiles;
+///
+/// let app = Application::new()
+///     .controller(SpaFiles::new("./frontend/dist").exclude("/auth"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpaFiles {
+    /// Build directory to serve
+    root: PathBuf,
+    /// File served for directories and client-side routes
+    index_file: String,
+    /// Path prefixes left to other controllers
+    exclude_prefixes: Vec<String>,
+    /// `max-age` in seconds for hashed assets
+    asset_max_age: u64,
+    /// Serve `.br`/`.gz` siblings
+    precompressed: bool,
+}
+
+impl SpaFiles {
+    /// Serve the build directory at `root`
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            index_file: "index.html".to_string(),
+            exclude_prefixes: vec!["/api".to_string()],
+            asset_max_age: 365 * 24 * 60 * 60,
+            precompressed: true,
+        }
+    }
+
+    /// Set the index file used for directories and the fallback
+    pub fn index_file<S: Into<String>>(mut self, name: S) -> Self {
+        self.index_file = name.into();
+        self
+    }
+
+    /// Add a path prefix that never falls back to the index file
+    pub fn exclude<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.exclude_prefixes.push(prefix.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Replace the excluded path prefixes
+    pub fn exclude_prefixes(mut self, prefixes: Vec<String>) -> Self {
+        self.exclude_prefixes = prefixes
+            .into_iter()
+            .map(|prefix| prefix.trim_end_matches('/').to_string())
+            .collect();
+        self
+    }
+
+    /// Set the `max-age` in seconds sent for hashed assets
+    pub fn asset_max_age(mut self, seconds: u64) -> Self {
+        self.asset_max_age = seconds;
+        self
+    }
+
+    /// Enable or disable serving precompressed `.br`/`.gz` siblings
+    pub fn precompressed(mut self, enabled: bool) -> Self {
+        self.precompressed = enabled;
+        self
+    }
+
+    /// Check if a request path belongs to an excluded prefix
+    fn is_excluded(&self, path: &str) -> bool {
+        self.exclude_prefixes.iter().any(|prefix| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    /// Serve a file, the index fallback or 404
+    fn serve(&self, req: &Request) -> Response {
+        let tail = req.param("tail").map(String::as_str).unwrap_or_default();
+        let path = match resolve(&self.root, tail, Some(&self.index_file)) {
+            Some(path) => path,
+            None if has_extension(tail) => return Response::not_found(),
+            None => match resolve(&self.root, "", Some(&self.index_file)) {
+                Some(path) => path,
+                None => return Response::not_found(),
+            },
+        };
+
+        let mut resp = Response::new();
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if file_name == self.index_file {
+            resp = resp.header("Cache-Control", "no-cache");
+        } else if is_hashed(file_name) {
+            resp = resp.header("Cache-Control", format!("public, max-age={}, immutable", self.asset_max_age));
+        }
+
+        if !self.precompressed {
+            return resp.file(path);
+        }
+        let siblings: Vec<_> = PRECOMPRESSED
+            .iter()
+            .filter_map(|(coding, extension)| {
+                let mut sibling = path.clone().into_os_string();
+                sibling.push(".");
+                sibling.push(extension);
+                within(&self.root, Path::new(&sibling))
+                    .filter(|sibling| sibling.is_file())
+                    .map(|sibling| (*coding, sibling))
+            })
+            .collect();
+        if siblings.is_empty() {
+            return resp.file(path);
+        }
+
+        resp = resp.append_header("Vary", "Accept-Encoding");
+        let accepted = req.header("accept-encoding").and_then(|header| negotiate(header, &siblings));
+        match accepted {
+            Some((coding, sibling)) => resp
+                .header("Content-Type", content_type(&path))
+                .header("Content-Encoding", *coding)
+                .file(sibling),
+            None => resp.file(path),
+        }
+    }
+}
+
+impl Controller for SpaFiles {
+    fn register_routes(&self, config: &mut web::ServiceConfig) {
+        let spa = Arc::new(self.clone());
+        let excluded = spa.clone();
+        let handler: HandlerFn = Arc::new(move |req: Request, _resp: Response| {
+            let spa = spa.clone();
+            Box::pin(async move { blocking(move || spa.serve(&req)).await })
+        });
+
+        // Excluded prefixes are filtered by a guard so they fall through to
+        // the controllers registered after this one
+        config.service(
+            web::resource("/{tail:.*}")
+                .guard(guard::Any(guard::Get()).or(guard::Head()))
+                .guard(guard::fn_guard(move |ctx| !excluded.is_excluded(ctx.head().uri.path())))
+                .to(move |req: HttpRequest, payload: web::Payload| {
+                    let handler = handler.clone();
+                    async move { dispatch(req, payload, handler).await }
+                }),
+        );
+    }
+}
+
+/// Build a file response on the blocking thread pool, as resolving and
+/// opening files touch the disk
+async fn blocking<F>(serve: F) -> Response
+where
+    F: FnOnce() -> Response + Send + 'static,
+{
+    web::block(serve).await.unwrap_or_else(|err| {
+        log::error!("Failed to serve file: {}", err);
+        Response::internal_error()
+    })
+}
+
+/// Check if the last segment of a URL path has a file extension
+fn has_extension(tail: &str) -> bool {
+    tail.rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .is_some_and(|(stem, extension)| !stem.is_empty() && !extension.is_empty())
+}
+
+/// Pick the precompressed sibling with the highest quality in
+/// `Accept-Encoding`
+fn negotiate<'a>(accept: &str, siblings: &'a [(&str, PathBuf)]) -> Option<&'a (&'a str, PathBuf)> {
+    let mut best: Option<(&(&str, PathBuf), f32)> = None;
+    for sibling in siblings {
+        let quality = encoding_quality(accept, sibling.0);
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((sibling, quality));
+        }
+    }
+    best.map(|(sibling, _)| sibling)
+}
+
+/// Check if a file name carries a content hash, as emitted by bundlers
+///
+/// Looks for a `.`- or `-`-separated segment between the stem and the
+/// extension of at least 8 hex digits, mixing digits and letters so that
+/// dates and version numbers (`report-20240101.js`) are not taken for one.
+fn is_hashed(name: &str) -> bool {
+    let segments: Vec<&str> = name.split(['.', '-']).collect();
+    segments.len() > 2
+        && segments[1..segments.len() - 1].iter().any(|segment| {
+            segment.len() >= 8
+                && segment.chars().all(|c| c.is_ascii_hexdigit())
+                && segment.chars().any(|c| c.is_ascii_digit())
+                && segment.chars().any(|c| c.is_ascii_alphabetic())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::App;
+
+    #[test]
+    fn ranges_are_parsed_against_the_file_size() {
+        assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Partial { start: 0, len: 100 });
+        assert_eq!(parse_range("bytes=900-", 1000), ByteRange::Partial { start: 900, len: 100 });
+        assert_eq!(parse_range("bytes=-100", 1000), ByteRange::Partial { start: 900, len: 100 });
+        assert_eq!(parse_range("bytes=990-2000", 1000), ByteRange::Partial { start: 990, len: 10 });
+        assert_eq!(parse_range("bytes=-5000", 1000), ByteRange::Partial { start: 0, len: 1000 });
+        assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=9-1", 1000), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 1000), ByteRange::Full);
+    }
+
+    #[test]
+    fn etags_match_weakly() {
+        assert!(etag_matches("\"a\"", "\"a\""));
+        assert!(etag_matches("W/\"a\"", "\"a\""));
+        assert!(etag_matches("\"x\", \"a\"", "W/\"a\""));
+        assert!(etag_matches("*", "\"a\""));
+        assert!(!etag_matches("\"b\"", "\"a\""));
+    }
+
+    #[test]
+    fn if_range_matches_strongly() {
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert!(if_range_matches("\"a\"", Some("\"a\""), None));
+        assert!(!if_range_matches("\"a\"", Some("W/\"a\""), None));
+        assert!(!if_range_matches("\"b\"", Some("\"a\""), None));
+        assert!(if_range_matches(date, None, Some(date)));
+        assert!(!if_range_matches("Thu, 22 Oct 2015 07:28:00 GMT", None, Some(date)));
+        assert!(!if_range_matches("not a date", None, Some(date)));
+    }
+
+    #[actix_web::test]
+    async fn static_files_answer_head_and_keep_headers_on_404() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello.txt"), "hello").unwrap();
+        let files = StaticFiles::new("/static", dir.path());
+        let app = init_service(App::new().configure(|cfg| files.register_routes(cfg))).await;
+
+        let req = TestRequest::default()
+            .method(actix_web::http::Method::HEAD)
+            .uri("/static/hello.txt")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("accept-ranges").unwrap(), "bytes");
+
+        let resp = call_service(&app, TestRequest::get().uri("/static/hello.txt").to_request()).await;
+        assert_eq!(read_body(resp).await, "hello");
+
+        let resp = Response::new().header("Cache-Control", "no-store").file(dir.path().join("missing.txt"));
+        assert_eq!(resp.get_status(), 404);
+        assert_eq!(resp.get_header("cache-control"), Some("no-store"));
+    }
+
+
+    /// Write `hello.txt` to a temp dir served under `/static`
+    fn hello_files() -> (tempfile::TempDir, StaticFiles) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello.txt"), "hello").unwrap();
+        let files = StaticFiles::new("/static", dir.path());
+        (dir, files)
+    }
+
+    #[actix_web::test]
+    async fn validators_answer_not_modified() {
+        let (_dir, files) = hello_files();
+        let app = init_service(App::new().configure(|cfg| files.register_routes(cfg))).await;
+        let resp = call_service(&app, TestRequest::get().uri("/static/hello.txt").to_request()).await;
+        let etag = resp.headers().get("etag").unwrap().clone();
+        let last_modified = resp.headers().get("last-modified").unwrap().clone();
+
+        let req = TestRequest::get().uri("/static/hello.txt").insert_header(("if-none-match", etag.clone()));
+        let resp = call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 304);
+        assert_eq!(resp.headers().get("etag"), Some(&etag));
+        assert!(read_body(resp).await.is_empty());
+
+        let req = TestRequest::get().uri("/static/hello.txt").insert_header(("if-modified-since", last_modified));
+        let resp = call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 304);
+
+        let req = TestRequest::get().uri("/static/hello.txt").insert_header(("if-none-match", "\"other\""));
+        let resp = call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(read_body(resp).await, "hello");
+    }
+
+    #[actix_web::test]
+    async fn single_ranges_answer_partial_content() {
+        let (_dir, files) = hello_files();
+        let app = init_service(App::new().configure(|cfg| files.register_routes(cfg))).await;
+
+        let req = TestRequest::get().uri("/static/hello.txt").insert_header(("range", "bytes=1-3"));
+        let resp = call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 206);
+        assert_eq!(resp.headers().get("content-range").unwrap(), "bytes 1-3/5");
+        assert_eq!(read_body(resp).await, "ell");
+
+        // A stale If-Range gets the whole file
+        let req = TestRequest::get()
+            .uri("/static/hello.txt")
+            .insert_header(("range", "bytes=1-3"))
+            .insert_header(("if-range", "\"stale\""));
+        let resp = call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(read_body(resp).await, "hello");
+    }
+
+    #[actix_web::test]
+    async fn ranges_past_the_end_are_unsatisfiable() {
+        let (_dir, files) = hello_files();
+        let app = init_service(App::new().configure(|cfg| files.register_routes(cfg))).await;
+
+        let req = TestRequest::get().uri("/static/hello.txt").insert_header(("range", "bytes=10-"));
+        let resp = call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 416);
+        assert_eq!(resp.headers().get("content-range").unwrap(), "bytes */5");
+        assert!(read_body(resp).await.is_empty());
+    }
+
+    #[test]
+    fn hashes_are_hex_segments() {
+        assert!(is_hashed("main.3f2a1b4c.js"));
+        assert!(is_hashed("index-4F3A9C1B.css"));
+        assert!(!is_hashed("report-20240101.js"));
+        assert!(!is_hashed("vendor-deadbeef.js"));
+        assert!(!is_hashed("index-BqL3x7Yz.js"));
+        assert!(!is_hashed("3f2a1b4c.js"));
+    }
+
+    /// Build directory with an index, a hashed asset and its precompressed
+    /// siblings, served by `SpaFiles` in front of an API controller
+    fn spa_build() -> (tempfile::TempDir, SpaFiles, BasicController) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<app>").unwrap();
+        std::fs::write(dir.path().join("main.3f2a1b4c.js"), "plain").unwrap();
+        std::fs::write(dir.path().join("main.3f2a1b4c.js.br"), "brotli").unwrap();
+        std::fs::write(dir.path().join("main.3f2a1b4c.js.gz"), "gzip").unwrap();
+        std::fs::write(dir.path().join("report-20240101.js"), "report").unwrap();
+        let spa = SpaFiles::new(dir.path());
+        let api = BasicController::new().get("/api/ping", |_req, _resp| Box::pin(async { Response::ok_text("pong") }));
+        (dir, spa, api)
+    }
+
+    #[actix_web::test]
+    async fn client_routes_fall_back_to_the_index() {
+        let (_dir, spa, api) = spa_build();
+        let app = init_service(App::new().configure(|cfg| {
+            spa.register_routes(cfg);
+            api.register_routes(cfg);
+        }))
+        .await;
+
+        let resp = call_service(&app, TestRequest::get().uri("/dashboard/settings").to_request()).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("cache-control").unwrap(), "no-cache");
+        assert_eq!(read_body(resp).await, "<app>");
+
+        let resp = call_service(&app, TestRequest::get().uri("/app.js").to_request()).await;
+        assert_eq!(resp.status(), 404);
+
+        let resp = call_service(&app, TestRequest::get().uri("/api/ping").to_request()).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(read_body(resp).await, "pong");
+    }
+
+    #[actix_web::test]
+    async fn hashed_assets_are_cached_and_precompressed() {
+        let (_dir, spa, api) = spa_build();
+        let app = init_service(App::new().configure(|cfg| {
+            spa.register_routes(cfg);
+            api.register_routes(cfg);
+        }))
+        .await;
+        let get = |accept: &str| {
+            TestRequest::get()
+                .uri("/main.3f2a1b4c.js")
+                .insert_header(("accept-encoding", accept))
+                .to_request()
+        };
+
+        let resp = call_service(&app, get("gzip, br")).await;
+        assert_eq!(resp.headers().get("cache-control").unwrap(), "public, max-age=31536000, immutable");
+        assert_eq!(resp.headers().get("vary").unwrap(), "Accept-Encoding");
+        assert_eq!(resp.headers().get("content-encoding").unwrap(), "br");
+        assert!(resp.headers().get("content-type").unwrap().to_str().unwrap().contains("javascript"));
+        assert_eq!(read_body(resp).await, "brotli");
+
+        let resp = call_service(&app, get("br;q=0.1, gzip")).await;
+        assert_eq!(resp.headers().get("content-encoding").unwrap(), "gzip");
+        assert_eq!(read_body(resp).await, "gzip");
+
+        let resp = call_service(&app, get("identity")).await;
+        assert!(resp.headers().get("content-encoding").is_none());
+        assert_eq!(resp.headers().get("vary").unwrap(), "Accept-Encoding");
+        assert_eq!(read_body(resp).await, "plain");
+
+        let resp = call_service(&app, TestRequest::get().uri("/report-20240101.js").to_request()).await;
+        assert!(resp.headers().get("cache-control").is_none());
+        assert_eq!(read_body(resp).await, "report");
+    }
+}
@@ -0,0 +1,176 @@
+//! Header module for dia framework
+//!
+//! Provides the HeaderMap struct shared by Request and Response.
+
+/// Ordered, case-insensitive, multi-value HTTP header map
+///
+/// Names keep the case they were inserted with but are compared
+/// case-insensitively. Values are stored as raw bytes so non-UTF-8 values
+/// survive; the `&str` accessors skip values that are not valid UTF-8.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::HeaderMap;
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert("Content-Type", "text/plain");
+/// headers.append("Set-Cookie", "a=1");
+/// headers.append("Set-Cookie", "b=2");
+///
+/// assert_eq!(headers.get("content-type"), Some("text/plain"));
+/// assert_eq!(headers.get_all("set-cookie"), vec!["a=1", "b=2"]);
+///
+/// headers.insert("set-cookie", "c=3");
+/// assert_eq!(headers.get_all("Set-Cookie"), vec!["c=3"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    /// Header entries in insertion order
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl HeaderMap {
+    /// Create an empty header map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the first value of a header as a string
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_bytes(name).and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Get the first value of a header as raw bytes
+    pub fn get_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Get every value of a header as strings, in order
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.get_all_bytes(name)
+            .into_iter()
+            .filter_map(|value| std::str::from_utf8(value).ok())
+            .collect()
+    }
+
+    /// Get every value of a header as raw bytes, in order
+    pub fn get_all_bytes(&self, name: &str) -> Vec<&[u8]> {
+        self.entries
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_slice())
+            .collect()
+    }
+
+    /// Check if a header is present
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.entries.iter().any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    /// Set a header, replacing any existing values
+    pub fn insert<K: Into<String>, V: Into<Vec<u8>>>(&mut self, name: K, value: V) {
+        let name = name.into();
+        self.remove(&name);
+        self.entries.push((name, value.into()));
+    }
+
+    /// Add a header value, keeping any existing values
+    pub fn append<K: Into<String>, V: Into<Vec<u8>>>(&mut self, name: K, value: V) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Remove every value of a header, returning whether any was present
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.entries.len() != before
+    }
+
+    /// Iterate over all header entries in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    /// Number of header entries, counting repeated names separately
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if there are no headers
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K: Into<String>, V: Into<Vec<u8>>> FromIterator<(K, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+impl<K: Into<String>, V: Into<Vec<u8>>> Extend<(K, V)> for HeaderMap {
+    /// Extend with `append` semantics
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
+impl From<&actix_web::http::header::HeaderMap> for HeaderMap {
+    fn from(headers: &actix_web::http::header::HeaderMap) -> Self {
+        headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_bytes()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_case_insensitively() {
+        let headers: HeaderMap = [("Content-Type", "text/html")].into_iter().collect();
+        assert_eq!(headers.get("content-type"), Some("text/html"));
+        assert_eq!(headers.get("CONTENT-TYPE"), Some("text/html"));
+        assert!(headers.contains_key("content-TYPE"));
+        assert_eq!(headers.iter().next(), Some(("Content-Type", b"text/html".as_slice())));
+    }
+
+    #[test]
+    fn append_keeps_values_and_insert_replaces_them() {
+        let mut headers = HeaderMap::new();
+        headers.append("Vary", "Origin");
+        headers.append("vary", "Accept-Encoding");
+        headers.append("X-Other", "1");
+        assert_eq!(headers.get_all("Vary"), vec!["Origin", "Accept-Encoding"]);
+        assert_eq!(headers.get("vary"), Some("Origin"));
+        assert_eq!(headers.len(), 3);
+
+        headers.insert("VARY", "Cookie");
+        assert_eq!(headers.get_all("vary"), vec!["Cookie"]);
+        assert_eq!(headers.get("x-other"), Some("1"));
+        assert_eq!(headers.len(), 2);
+    }
+
+    #[test]
+    fn non_utf8_values_are_kept_as_bytes() {
+        let mut headers = HeaderMap::new();
+        headers.append("X-Name", b"caf\xe9".to_vec());
+        headers.append("X-Name", "plain");
+
+        assert_eq!(headers.get_bytes("x-name"), Some(b"caf\xe9".as_slice()));
+        assert_eq!(headers.get_all_bytes("x-name"), vec![b"caf\xe9".as_slice(), b"plain".as_slice()]);
+        // String accessors skip what is not UTF-8
+        assert_eq!(headers.get("x-name"), None);
+        assert_eq!(headers.get_all("x-name"), vec!["plain"]);
+    }
+}
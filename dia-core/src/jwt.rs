@@ -0,0 +1,421 @@
+//! JWT module for dia framework
+//!
+//! Provides JSON Web Token key material shared by AuthMiddleware and
+//! TokenService, and the Claims type handed to handlers.
+
+pub use jsonwebtoken::Algorithm;
+
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+/// Default clock skew tolerated for `exp` and `nbf`, in seconds
+pub const DEFAULT_LEEWAY: u64 = 60;
+
+/// Claim marking refresh tokens, which are never accepted as access tokens
+pub(crate) const TOKEN_TYPE_CLAIM: &str = "token_type";
+
+/// Value of `token_type` for refresh tokens
+pub(crate) const REFRESH_TOKEN_TYPE: &str = "refresh";
+
+/// Reasons a bearer token was rejected
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum JwtError {
+    /// No bearer token in the request
+    #[error("Authentication required")]
+    Missing,
+    /// Not a well-formed JWT
+    #[error("Malformed token")]
+    Malformed,
+    /// Signature does not match the key, or the algorithm is not accepted
+    #[error("Invalid token signature")]
+    InvalidSignature,
+    /// `exp` is in the past
+    #[error("Token expired")]
+    Expired,
+    /// `nbf` is in the future
+    #[error("Token not yet valid")]
+    NotYetValid,
+    /// `iss` does not match the configured issuer
+    #[error("Invalid token issuer")]
+    InvalidIssuer,
+    /// `aud` does not contain the configured audience
+    #[error("Invalid token audience")]
+    InvalidAudience,
+    /// A required claim is absent
+    #[error("Missing required claim '{0}'")]
+    MissingClaim(String),
+    /// The configured key could not be loaded
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    /// A refresh token was sent as an access token, or the reverse
+    #[error("Wrong token type")]
+    WrongTokenType,
+    /// The token was revoked
+    #[error("Token revoked")]
+    Revoked,
+    /// Signing requested on a config holding only a public key
+    #[error("No signing key configured")]
+    NoSigningKey,
+    /// Claims could not be serialized into a JSON object
+    #[error("Invalid claims: {0}")]
+    InvalidClaims(String),
+}
+
+impl From<jsonwebtoken::errors::Error> for JwtError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        match err.into_kind() {
+            ErrorKind::InvalidSignature | ErrorKind::InvalidAlgorithm => JwtError::InvalidSignature,
+            ErrorKind::ExpiredSignature => JwtError::Expired,
+            ErrorKind::ImmatureSignature => JwtError::NotYetValid,
+            ErrorKind::InvalidIssuer => JwtError::InvalidIssuer,
+            ErrorKind::InvalidAudience => JwtError::InvalidAudience,
+            ErrorKind::MissingRequiredClaim(claim) => JwtError::MissingClaim(claim),
+            ErrorKind::InvalidEcdsaKey | ErrorKind::InvalidKeyFormat => {
+                JwtError::InvalidKey("unsupported key format".to_string())
+            }
+            ErrorKind::InvalidRsaKey(reason) => JwtError::InvalidKey(reason),
+            _ => JwtError::Malformed,
+        }
+    }
+}
+
+/// Verified claims of a bearer token, available from `Request::claims`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims {
+    /// Decoded payload
+    value: Value,
+}
+
+impl Claims {
+    /// Get a claim by name
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.value.get(name)
+    }
+
+    /// Get the subject (`sub`)
+    pub fn subject(&self) -> Option<&str> {
+        self.get("sub").and_then(Value::as_str)
+    }
+
+    /// Get the issuer (`iss`)
+    pub fn issuer(&self) -> Option<&str> {
+        self.get("iss").and_then(Value::as_str)
+    }
+
+    /// Get the expiry time (`exp`) in seconds since the Unix epoch
+    pub fn expires_at(&self) -> Option<u64> {
+        self.get("exp").and_then(Value::as_u64)
+    }
+
+    /// Get the whole payload
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Deserialize the payload into a typed claims struct
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        T::deserialize(&self.value)
+    }
+}
+
+/// Type of key, which decides the algorithms a config can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyFamily {
+    /// Shared secret
+    Hmac,
+    /// RSA key pair
+    Rsa,
+    /// Elliptic curve key pair
+    Ec,
+}
+
+impl KeyFamily {
+    /// Key type an algorithm needs, or `None` if no constructor provides it
+    fn of(algorithm: Algorithm) -> Option<Self> {
+        match algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => Some(KeyFamily::Hmac),
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => Some(KeyFamily::Rsa),
+            Algorithm::ES256 | Algorithm::ES384 => Some(KeyFamily::Ec),
+            Algorithm::EdDSA => None,
+        }
+    }
+}
+
+/// Verification key and validation rules for bearer tokens
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::jwt::{Algorithm, JwtConfig};
+///
+/// let config = JwtConfig::hmac("secret")
+///     .algorithm(Algorithm::HS512)?
+///     .issuer("https://auth.example.com")
+///     .audience("my-api")
+///     .leeway(30);
+/// # Ok::<(), dia_core::jwt::JwtError>(())
+/// ```
+#[derive(Clone)]
+pub struct JwtConfig {
+    /// Accepted signing algorithm
+    algorithm: Algorithm,
+    /// Type of the configured keys
+    family: KeyFamily,
+    /// Key used to verify signatures
+    decoding_key: DecodingKey,
+    /// Key used to sign tokens, if available
+    encoding_key: Option<EncodingKey>,
+    /// Expected `iss`
+    issuer: Option<String>,
+    /// Accepted `aud` values
+    audience: Vec<String>,
+    /// Clock skew tolerated for `exp` and `nbf`, in seconds
+    leeway: u64,
+}
+
+impl JwtConfig {
+    /// Sign and verify HS256 tokens with a shared secret
+    ///
+    /// Use `algorithm` to switch to HS384 or HS512.
+    pub fn hmac<S: AsRef<[u8]>>(secret: S) -> Self {
+        let mut config = Self::with_key(
+            Algorithm::HS256,
+            KeyFamily::Hmac,
+            DecodingKey::from_secret(secret.as_ref()),
+        );
+        config.encoding_key = Some(EncodingKey::from_secret(secret.as_ref()));
+        config
+    }
+
+    /// Verify RS256 tokens with a PEM-encoded RSA public key
+    pub fn rsa_pem<K: AsRef<[u8]>>(public_key: K) -> Result<Self, JwtError> {
+        let key = DecodingKey::from_rsa_pem(public_key.as_ref())?;
+        Ok(Self::with_key(Algorithm::RS256, KeyFamily::Rsa, key))
+    }
+
+    /// Verify ES256 tokens with a PEM-encoded EC (P-256) public key
+    pub fn ec_pem<K: AsRef<[u8]>>(public_key: K) -> Result<Self, JwtError> {
+        let key = DecodingKey::from_ec_pem(public_key.as_ref())?;
+        Ok(Self::with_key(Algorithm::ES256, KeyFamily::Ec, key))
+    }
+
+    /// Create a config from a verification key
+    fn with_key(algorithm: Algorithm, family: KeyFamily, decoding_key: DecodingKey) -> Self {
+        Self {
+            algorithm,
+            family,
+            decoding_key,
+            encoding_key: None,
+            issuer: None,
+            audience: Vec::new(),
+            leeway: DEFAULT_LEEWAY,
+        }
+    }
+
+    /// Add the PEM-encoded private key used to sign RS256/ES256 tokens
+    ///
+    /// Only needed to issue tokens; verification uses the public key.
+    pub fn signing_key_pem<K: AsRef<[u8]>>(mut self, private_key: K) -> Result<Self, JwtError> {
+        let key = match self.family {
+            KeyFamily::Rsa => EncodingKey::from_rsa_pem(private_key.as_ref())?,
+            KeyFamily::Ec => EncodingKey::from_ec_pem(private_key.as_ref())?,
+            KeyFamily::Hmac => {
+                return Err(JwtError::InvalidKey("HMAC configs sign with their secret".to_string()))
+            }
+        };
+        self.encoding_key = Some(key);
+        Ok(self)
+    }
+
+    /// Set the accepted algorithm
+    ///
+    /// Fails with `JwtError::InvalidKey` if the algorithm needs a different
+    /// type of key than the config holds, e.g. RS256 on an HMAC secret.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Result<Self, JwtError> {
+        if KeyFamily::of(algorithm) != Some(self.family) {
+            return Err(JwtError::InvalidKey(format!(
+                "{:?} cannot be used with {:?} keys",
+                algorithm, self.family
+            )));
+        }
+        self.algorithm = algorithm;
+        Ok(self)
+    }
+
+    /// Require the `iss` claim to equal `issuer`
+    pub fn issuer<S: Into<String>>(mut self, issuer: S) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Require the `aud` claim to contain `audience`
+    ///
+    /// Can be called several times to accept any of several audiences.
+    pub fn audience<S: Into<String>>(mut self, audience: S) -> Self {
+        self.audience.push(audience.into());
+        self
+    }
+
+    /// Set the clock skew tolerated for `exp` and `nbf`, in seconds
+    pub fn leeway(mut self, seconds: u64) -> Self {
+        self.leeway = seconds;
+        self
+    }
+
+    /// Get the expected issuer
+    pub fn get_issuer(&self) -> Option<&str> {
+        self.issuer.as_deref()
+    }
+
+    /// Get the accepted audiences
+    pub fn get_audience(&self) -> &[String] {
+        &self.audience
+    }
+
+    /// Sign claims into a token
+    ///
+    /// The claims are written as-is; use `TokenService` to add expiry and the
+    /// other registered claims.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
+        let key = self.encoding_key.as_ref().ok_or(JwtError::NoSigningKey)?;
+        Ok(jsonwebtoken::encode(&Header::new(self.algorithm), claims, key)?)
+    }
+
+    /// Verify an access token's signature and registered claims
+    ///
+    /// `exp` is always required, `iss` and `aud` when configured; `nbf` is
+    /// checked when present. Refresh tokens are rejected.
+    pub fn validate(&self, token: &str) -> Result<Claims, JwtError> {
+        let claims = self.decode(token)?;
+        if claims.get(TOKEN_TYPE_CLAIM).and_then(Value::as_str) == Some(REFRESH_TOKEN_TYPE) {
+            return Err(JwtError::WrongTokenType);
+        }
+        Ok(claims)
+    }
+
+    /// Verify a refresh token issued by `TokenService`
+    pub(crate) fn validate_refresh(&self, token: &str) -> Result<Claims, JwtError> {
+        let claims = self.decode(token)?;
+        if claims.get(TOKEN_TYPE_CLAIM).and_then(Value::as_str) != Some(REFRESH_TOKEN_TYPE) {
+            return Err(JwtError::WrongTokenType);
+        }
+        Ok(claims)
+    }
+
+    /// Verify a token's signature and registered claims
+    fn decode(&self, token: &str) -> Result<Claims, JwtError> {
+        let mut validation = Validation::new(self.algorithm);
+        let mut required = vec!["exp"];
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+        validation.validate_aud = !self.audience.is_empty();
+        if !self.audience.is_empty() {
+            validation.set_audience(&self.audience);
+            required.push("aud");
+        }
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        validation.set_required_spec_claims(&required);
+
+        let data = jsonwebtoken::decode::<Value>(token, &self.decoding_key, &validation)?;
+        Ok(Claims { value: data.claims })
+    }
+}
+
+impl std::fmt::Debug for JwtConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtConfig")
+            .field("algorithm", &self.algorithm)
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("leeway", &self.leeway)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn signature_and_algorithm_are_checked() {
+        let config = JwtConfig::hmac("secret");
+        let claims = json!({ "sub": "alice", "exp": now() + 60 });
+
+        let token = config.sign(&claims).unwrap();
+        assert_eq!(config.validate(&token).unwrap().subject(), Some("alice"));
+
+        let forged = JwtConfig::hmac("other").sign(&claims).unwrap();
+        assert_eq!(config.validate(&forged), Err(JwtError::InvalidSignature));
+
+        let hs512 = JwtConfig::hmac("secret").algorithm(Algorithm::HS512).unwrap();
+        assert_eq!(config.validate(&hs512.sign(&claims).unwrap()), Err(JwtError::InvalidSignature));
+        assert_eq!(config.validate("not.a.jwt"), Err(JwtError::Malformed));
+    }
+
+    #[test]
+    fn expiry_and_not_before_allow_the_leeway() {
+        let config = JwtConfig::hmac("secret");
+        let strict = JwtConfig::hmac("secret").leeway(0);
+
+        let expired = config.sign(&json!({ "exp": now() - 30 })).unwrap();
+        assert!(config.validate(&expired).is_ok());
+        assert_eq!(strict.validate(&expired), Err(JwtError::Expired));
+
+        let early = config.sign(&json!({ "exp": now() + 600, "nbf": now() + 30 })).unwrap();
+        assert!(config.validate(&early).is_ok());
+        assert_eq!(strict.validate(&early), Err(JwtError::NotYetValid));
+
+        let forever = config.sign(&json!({ "sub": "alice" })).unwrap();
+        assert_eq!(config.validate(&forever), Err(JwtError::MissingClaim("exp".to_string())));
+    }
+
+    #[test]
+    fn issuer_and_audience_are_required_when_configured() {
+        let config = JwtConfig::hmac("secret").issuer("https://auth.example.com").audience("api");
+        let sign = |claims| config.sign(&claims).unwrap();
+        let exp = now() + 60;
+
+        let valid = sign(json!({ "exp": exp, "iss": "https://auth.example.com", "aud": ["web", "api"] }));
+        assert!(config.validate(&valid).is_ok());
+
+        let issuer = sign(json!({ "exp": exp, "iss": "https://evil.com", "aud": "api" }));
+        assert_eq!(config.validate(&issuer), Err(JwtError::InvalidIssuer));
+
+        let audience = sign(json!({ "exp": exp, "iss": "https://auth.example.com", "aud": "web" }));
+        assert_eq!(config.validate(&audience), Err(JwtError::InvalidAudience));
+
+        let missing = sign(json!({ "exp": exp, "aud": "api" }));
+        assert_eq!(config.validate(&missing), Err(JwtError::MissingClaim("iss".to_string())));
+    }
+
+    #[test]
+    fn algorithm_must_match_the_key_type() {
+        assert!(JwtConfig::hmac("secret").algorithm(Algorithm::HS384).is_ok());
+        assert!(matches!(
+            JwtConfig::hmac("secret").algorithm(Algorithm::RS256),
+            Err(JwtError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            JwtConfig::hmac("secret").algorithm(Algorithm::EdDSA),
+            Err(JwtError::InvalidKey(_))
+        ));
+    }
+}
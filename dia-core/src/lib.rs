@@ -7,17 +7,36 @@
 
 pub mod application;
 pub mod request;
+pub mod query;
+pub mod header;
+pub mod extensions;
+pub mod cookie;
+pub mod multipart;
+pub mod files;
 pub mod response;
 pub mod controller;
 pub mod middleware;
+pub mod jwt;
+pub mod token;
+pub mod path_pattern;
+pub mod access_log;
+pub mod rate_limit;
+pub mod compression;
 pub mod ffi;
 
 // Re-export main types for easier access
-pub use application::Application;
+pub use application::{Application, Server};
 pub use request::Request;
+pub use query::QueryParams;
+pub use header::HeaderMap;
+pub use extensions::Extensions;
+pub use cookie::{Cookie, CookieKey, SameSite};
+pub use files::{SpaFiles, StaticFiles};
 pub use response::Response;
 pub use controller::{Controller, BasicController, Route};
-pub use middleware::Middleware;
+pub use middleware::{Middleware, MiddlewareChain};
+pub use path_pattern::{PathPattern, PublicPaths};
+pub use token::TokenService;
 
 // Re-export macros from dia-macros
 pub use dia_macros::*;
@@ -43,6 +62,7 @@ pub extern "C" fn dia_version() -> *const c_char {
 
 /// Free a C string returned by dia functions
 #[unsafe(no_mangle)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn dia_free_string(s: *mut c_char) {
     if !s.is_null() {
         unsafe {
@@ -2,26 +2,47 @@
 //! 
 //! Provides the Middleware trait and common middleware implementations.
 
+use crate::access_log::SensitiveQuery;
+use crate::controller::HandlerFn;
+use crate::jwt::{JwtConfig, JwtError};
+use crate::path_pattern::{PathPattern, PatternError, PublicPaths};
+use crate::response::ResponseBody;
 use crate::{Request, Response};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha2::{Digest, Sha256};
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
 
 /// Trait for implementing middleware
+///
+/// The hooks' futures may borrow the middleware and the request for `'a`.
+/// Middlewares written against 0.1.x need the lifetime added to their
+/// signatures; see `CHANGELOG.md`.
 pub trait Middleware: Send + Sync {
     /// Process the request before it reaches the handler
-    fn before_request(
-        &self,
-        req: &mut Request,
-    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send>> {
+    ///
+    /// Returning a response short-circuits the chain. The future may borrow
+    /// the request, so values computed asynchronously can be attached to it.
+    ///
+    /// The request body is read only once every middleware has let the
+    /// request through, so it is still empty here; it is available in
+    /// `after_request`.
+    fn before_request<'a>(
+        &'a self,
+        _req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
         Box::pin(async { None })
     }
 
     /// Process the response after the handler
-    fn after_request(
-        &self,
-        req: &Request,
+    fn after_request<'a>(
+        &'a self,
+        _req: &'a Request,
         resp: Response,
-    ) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
         Box::pin(async { resp })
     }
 
@@ -29,9 +50,181 @@ pub trait Middleware: Send + Sync {
     fn name(&self) -> &str {
         "unknown"
     }
+
+    /// Check the configuration before the server starts
+    ///
+    /// An error stops `Application::run` and `Application::start`, so
+    /// settings that only conflict in combination are reported regardless of
+    /// the order the builder methods were called in.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Middleware configuration rejected by `Middleware::validate`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid {middleware} middleware: {reason}")]
+pub struct ConfigError {
+    /// Name of the middleware
+    pub middleware: String,
+    /// What is wrong with its configuration
+    pub reason: String,
+}
+
+/// Validate middlewares in order, reporting the first invalid one
+pub(crate) fn validate_all<'a, I>(middlewares: I) -> Result<(), ConfigError>
+where
+    I: IntoIterator<Item = &'a dyn Middleware>,
+{
+    for middleware in middlewares {
+        middleware.validate().map_err(|reason| ConfigError {
+            middleware: middleware.name().to_string(),
+            reason,
+        })?;
+    }
+    Ok(())
+}
+
+/// Shared middlewares, so one instance can be registered in several places
+impl<M: Middleware + ?Sized> Middleware for Arc<M> {
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        (**self).before_request(req)
+    }
+
+    fn after_request<'a>(
+        &'a self,
+        req: &'a Request,
+        resp: Response,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
+        (**self).after_request(req, resp)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        (**self).validate()
+    }
+}
+
+/// Ordered chain of middlewares wrapped around every route handler
+///
+/// Middlewares run onion-style: `before_request` is called in registration
+/// order, `after_request` in reverse order, so the first registered
+/// middleware is the outermost layer. When a middleware short-circuits by
+/// returning a `Response` from `before_request`, the handler and the inner
+/// middlewares are skipped, and only the outer middlewares that already ran
+/// see the response in `after_request`.
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+    /// Middlewares in registration order
+    middlewares: Arc<Vec<Box<dyn Middleware>>>,
+}
+
+impl MiddlewareChain {
+    /// Create a chain from middlewares in registration order
+    pub fn new(middlewares: Vec<Box<dyn Middleware>>) -> Self {
+        Self {
+            middlewares: Arc::new(middlewares),
+        }
+    }
+
+    /// Number of middlewares in the chain
+    pub fn len(&self) -> usize {
+        self.middlewares.len()
+    }
+
+    /// Check if the chain has no middlewares
+    pub fn is_empty(&self) -> bool {
+        self.middlewares.is_empty()
+    }
+
+    /// Validate every middleware's configuration
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_all(self.middlewares.iter().map(AsRef::as_ref))
+    }
+
+    /// Run a request through the chain and the given handler
+    ///
+    /// The handler's response goes through `Response::conditional` before the
+    /// `after_request` hooks run, so they see the final 304/206 status.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use dia_core::middleware::{AuthMiddleware, MiddlewareChain};
+    /// use dia_core::{Request, Response};
+    /// use actix_web::test::TestRequest;
+    /// use std::sync::Arc;
+    ///
+    /// let auth = AuthMiddleware::new("secret");
+    /// let chain = MiddlewareChain::new(vec![Box::new(auth)]);
+    /// let req = Request::new(TestRequest::get().uri("/admin").to_http_request());
+    /// let handler = Arc::new(|_req, _resp| Box::pin(async { Response::ok_text("secret data") }) as _);
+    ///
+    /// let resp = futures::executor::block_on(chain.handle(req, handler));
+    /// assert_eq!(resp.into_http_response().status(), 401);
+    /// ```
+    pub async fn handle(&self, mut req: Request, handler: HandlerFn) -> Response {
+        let (entered, short_circuit) = self.before(&mut req).await;
+        let resp = match short_circuit {
+            Some(resp) => resp,
+            None => handler(req.clone(), Response::new()).await.conditional(&req),
+        };
+        self.after(&req, entered, resp).await
+    }
+
+    /// Run the `before_request` hooks in order
+    ///
+    /// Returns how many middlewares let the request through, and the response
+    /// of the one that short-circuited, if any.
+    pub(crate) async fn before(&self, req: &mut Request) -> (usize, Option<Response>) {
+        let mut entered = 0;
+        for middleware in self.middlewares.iter() {
+            if let Some(resp) = middleware.before_request(req).await {
+                log::debug!("Middleware {} short-circuited {} {}", middleware.name(), req.method(), req.path());
+                return (entered, Some(resp));
+            }
+            entered += 1;
+        }
+        (entered, None)
+    }
+
+    /// Run the `after_request` hooks of the first `entered` middlewares, in
+    /// reverse order
+    pub(crate) async fn after(&self, req: &Request, entered: usize, mut resp: Response) -> Response {
+        for middleware in self.middlewares[..entered].iter().rev() {
+            resp = middleware.after_request(req, resp).await;
+        }
+        resp
+    }
 }
 
 /// CORS middleware for handling cross-origin requests
+///
+/// Answers `OPTIONS` preflights itself and adds the CORS headers to actual
+/// responses. The request's `Origin` is reflected when it matches an allowed
+/// origin, which can be exact (`https://app.example.com`), a wildcard
+/// subdomain (`https://*.example.com`) or `*` for any origin.
+///
+/// Credentials cannot be combined with the `*` origin, which browsers reject;
+/// `Middleware::validate` reports it, so the application fails to start.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::middleware::CorsMiddleware;
+///
+/// let cors = CorsMiddleware::new()
+///     .allowed_origins(vec!["https://app.example.com".to_string(), "https://*.example.dev".to_string()])
+///     .allow_credentials(true)
+///     .expose_headers(vec!["X-Request-Id".to_string()])
+///     .max_age(600);
+/// ```
 pub struct CorsMiddleware {
     /// Allowed origins
     allowed_origins: Vec<String>,
@@ -39,8 +232,12 @@ pub struct CorsMiddleware {
     allowed_methods: Vec<String>,
     /// Allowed headers
     allowed_headers: Vec<String>,
+    /// Response headers readable by scripts
+    expose_headers: Vec<String>,
     /// Allow credentials
     allow_credentials: bool,
+    /// How long browsers may cache preflight results, in seconds
+    max_age: Option<u64>,
 }
 
 impl CorsMiddleware {
@@ -50,7 +247,9 @@ impl CorsMiddleware {
             allowed_origins: vec!["*".to_string()],
             allowed_methods: vec!["GET".to_string(), "POST".to_string(), "PUT".to_string(), "DELETE".to_string()],
             allowed_headers: vec!["*".to_string()],
+            expose_headers: Vec::new(),
             allow_credentials: false,
+            max_age: None,
         }
     }
 
@@ -72,34 +271,142 @@ impl CorsMiddleware {
         self
     }
 
+    /// Set the response headers scripts are allowed to read
+    pub fn expose_headers(mut self, headers: Vec<String>) -> Self {
+        self.expose_headers = headers;
+        self
+    }
+
     /// Enable credentials
+    ///
+    /// Browsers refuse credentials for the `*` origin, so the allowed origins
+    /// must be listed; otherwise the application fails to start.
     pub fn allow_credentials(mut self, allow: bool) -> Self {
         self.allow_credentials = allow;
         self
     }
+
+    /// Set how long browsers may cache preflight results, in seconds
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Check if every origin is allowed
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    /// Check if a request origin matches an allowed origin pattern
+    fn is_allowed_origin(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        self.allowed_origins.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            match pattern.split_once('*') {
+                None => pattern == origin,
+                Some(("", "")) => true,
+                Some((prefix, suffix)) => {
+                    origin.len() > prefix.len() + suffix.len()
+                        && origin.starts_with(prefix)
+                        && origin.ends_with(suffix)
+                        && !origin[prefix.len()..origin.len() - suffix.len()].contains(['/', ':', '@'])
+                }
+            }
+        })
+    }
+
+    /// Value of `Access-Control-Allow-Origin` for an allowed origin
+    fn allow_origin_value(&self, origin: &str) -> String {
+        if self.allows_any_origin() {
+            "*".to_string()
+        } else {
+            origin.to_string()
+        }
+    }
+
+    /// Answer a preflight request
+    fn preflight(&self, req: &Request, origin: &str, method: &str) -> Response {
+        let method_allowed = self
+            .allowed_methods
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(method));
+        let requested_headers = req.header("access-control-request-headers").unwrap_or_default();
+        let any_header = self.allowed_headers.iter().any(|header| header == "*");
+        let headers_allowed = any_header
+            || requested_headers
+                .split(',')
+                .map(str::trim)
+                .filter(|header| !header.is_empty())
+                .all(|header| self.allowed_headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(header)));
+
+        let vary = "Origin, Access-Control-Request-Method, Access-Control-Request-Headers";
+        if !self.is_allowed_origin(origin) || !method_allowed || !headers_allowed {
+            log::debug!("Rejected CORS preflight from {} for {} {}", origin, method, req.path());
+            return Response::forbidden("CORS preflight rejected").header("Vary", vary);
+        }
+
+        // `*` is not honoured for credentialed requests, so reflect instead
+        let allow_headers = if any_header {
+            requested_headers.to_string()
+        } else {
+            self.allowed_headers.join(", ")
+        };
+        let mut resp = Response::new()
+            .status(204)
+            .header("Access-Control-Allow-Origin", self.allow_origin_value(origin))
+            .header("Access-Control-Allow-Methods", self.allowed_methods.join(", "))
+            .header("Vary", vary);
+        if !allow_headers.is_empty() {
+            resp = resp.header("Access-Control-Allow-Headers", allow_headers);
+        }
+        if self.allow_credentials {
+            resp = resp.header("Access-Control-Allow-Credentials", "true");
+        }
+        if let Some(max_age) = self.max_age {
+            resp = resp.header("Access-Control-Max-Age", max_age.to_string());
+        }
+        resp
+    }
 }
 
 impl Middleware for CorsMiddleware {
-    fn after_request(
-        &self,
-        _req: &Request,
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        // A preflight short-circuits the chain, so this middleware's
+        // after_request never sees its own preflight response
+        let preflight = match (req.method(), req.header("origin"), req.header("access-control-request-method")) {
+            ("OPTIONS", Some(origin), Some(method)) => Some(self.preflight(req, origin, method)),
+            _ => None,
+        };
+        Box::pin(async move { preflight })
+    }
+
+    fn after_request<'a>(
+        &'a self,
+        req: &'a Request,
         resp: Response,
-    ) -> Pin<Box<dyn Future<Output = Response> + Send>> {
-        let allowed_origins = self.allowed_origins.join(", ");
-        let allowed_methods = self.allowed_methods.join(", ");
-        let allowed_headers = self.allowed_headers.join(", ");
-        let allow_credentials = self.allow_credentials;
-        
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
         Box::pin(async move {
-            let mut resp = resp
-                .header("Access-Control-Allow-Origin", allowed_origins)
-                .header("Access-Control-Allow-Methods", allowed_methods)
-                .header("Access-Control-Allow-Headers", allowed_headers);
+            let Some(origin) = req.header("origin") else {
+                return resp;
+            };
+            let mut resp = resp;
+            if !self.allows_any_origin() {
+                resp = resp.append_header("Vary", "Origin");
+            }
+            if !self.is_allowed_origin(origin) {
+                return resp;
+            }
 
-            if allow_credentials {
+            resp = resp.header("Access-Control-Allow-Origin", self.allow_origin_value(origin));
+            if self.allow_credentials {
                 resp = resp.header("Access-Control-Allow-Credentials", "true");
             }
-            
+            if !self.expose_headers.is_empty() {
+                resp = resp.header("Access-Control-Expose-Headers", self.expose_headers.join(", "));
+            }
             resp
         })
     }
@@ -107,6 +414,13 @@ impl Middleware for CorsMiddleware {
     fn name(&self) -> &str {
         "CORS"
     }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.allows_any_origin() {
+            return Err("credentials cannot be allowed for the '*' origin; list the allowed origins instead".to_string());
+        }
+        Ok(())
+    }
 }
 
 impl Default for CorsMiddleware {
@@ -145,46 +459,65 @@ impl LoggingMiddleware {
     }
 }
 
+/// Time at which LoggingMiddleware saw the request
+struct RequestStart(Instant);
+
 impl Middleware for LoggingMiddleware {
-    fn before_request(
-        &self,
-        req: &mut Request,
-    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send>> {
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        req.extensions_mut().insert(RequestStart(Instant::now()));
         let method = req.method().to_string();
         let path = req.path().to_string();
         let remote_ip = req.remote_ip().cloned().unwrap_or_else(|| "unknown".to_string());
-        let log_bodies = self.log_bodies;
-        let body = req.json().cloned();
-        
+
         Box::pin(async move {
             log::info!("{} {} - {}", method, path, remote_ip);
-
-            if log_bodies {
-                if let Some(body) = body {
-                    log::debug!("Request body: {}", serde_json::to_string_pretty(&body).unwrap_or_default());
-                }
-            }
-
             None
         })
     }
 
-    fn after_request(
-        &self,
-        req: &Request,
+    fn after_request<'a>(
+        &'a self,
+        req: &'a Request,
         resp: Response,
-    ) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
         let method = req.method().to_string();
         let path = req.path().to_string();
         let log_responses = self.log_responses;
-        
+        let latency = req.extensions().get::<RequestStart>().map(|start| start.0.elapsed());
+        // The body is read after `before_request`, so it is logged here
+        let body = req.json().filter(|_| self.log_bodies).cloned();
+
         Box::pin(async move {
-            log::info!("Response for {} {} - Status: {}", method, path, "200"); // TODO: Get actual status from response
+            if let Some(body) = body {
+                log::debug!("Request body: {}", serde_json::to_string_pretty(&body).unwrap_or_default());
+            }
+
+            let size = resp
+                .get_body_len()
+                .map_or_else(|| "-".to_string(), |len| len.to_string());
+            let latency = latency.map_or_else(|| "-".to_string(), |latency| format!("{:.2?}", latency));
+            log::info!(
+                "Response for {} {} - Status: {}, Size: {} bytes, Time: {}",
+                method,
+                path,
+                resp.get_status(),
+                size,
+                latency
+            );
 
             if log_responses {
-                log::debug!("Response sent for {} {}", method, path);
+                match resp.get_body() {
+                    ResponseBody::Text(text) => log::debug!("Response body: {}", text),
+                    ResponseBody::Json(json) => {
+                        log::debug!("Response body: {}", serde_json::to_string_pretty(json).unwrap_or_default())
+                    }
+                    body => log::debug!("Response body: <{}>", body.kind()),
+                }
             }
-            
+
             resp
         })
     }
@@ -201,63 +534,785 @@ impl Default for LoggingMiddleware {
 }
 
 /// Authentication middleware
+///
+/// Requires a valid `Authorization: Bearer <jwt>` header on every non-public
+/// path. The verified claims are stored on the request (see
+/// `Request::claims`), with the `sub` claim as its `Principal`; rejected
+/// requests get a 401 with a `WWW-Authenticate` header describing the error.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::middleware::AuthMiddleware;
+/// use dia_core::jwt::{Algorithm, JwtConfig};
+///
+/// // HS256 with a shared secret
+/// let auth = AuthMiddleware::new("secret");
+///
+/// // HS512 with issuer and audience checks
+/// let auth = AuthMiddleware::with_jwt(
+///     JwtConfig::hmac("secret")
+///         .algorithm(Algorithm::HS512)?
+///         .issuer("https://auth.example.com")
+///         .audience("my-api"),
+/// );
+/// # Ok::<(), dia_core::jwt::JwtError>(())
+/// ```
 pub struct AuthMiddleware {
-    /// Paths that don't require authentication
-    public_paths: Vec<String>,
-    /// JWT secret key
-    secret_key: String,
+    /// Requests that don't require authentication
+    public_paths: PublicPaths,
+    /// Token verification settings
+    jwt: JwtConfig,
+    /// Realm reported in `WWW-Authenticate`
+    realm: String,
 }
 
 impl AuthMiddleware {
-    /// Create a new auth middleware
+    /// Create a new auth middleware verifying HS256 tokens with a secret
     pub fn new<S: Into<String>>(secret_key: S) -> Self {
+        Self::with_jwt(JwtConfig::hmac(secret_key.into()))
+    }
+
+    /// Create a new auth middleware with full token verification settings
+    pub fn with_jwt(jwt: JwtConfig) -> Self {
         Self {
-            public_paths: vec!["/health".to_string(), "/".to_string()],
-            secret_key: secret_key.into(),
+            public_paths: PublicPaths::default(),
+            jwt,
+            realm: "dia".to_string(),
         }
     }
 
-    /// Add public paths that don't require authentication
-    pub fn public_paths(mut self, paths: Vec<String>) -> Self {
-        self.public_paths = paths;
+    /// Set the requests that don't require authentication
+    ///
+    /// Replaces the default, which only makes `/health` public. See
+    /// `PathPattern` for the syntax: `/` is an exact match, `/docs/**` covers
+    /// a subtree, and `GET /docs/**` restricts the rule to a method.
+    ///
+    /// Accepts pattern strings or parsed `PathPattern`s, and fails if a
+    /// string is not a valid pattern.
+    pub fn public_paths<I, P>(mut self, paths: I) -> Result<Self, PatternError>
+    where
+        I: IntoIterator<Item = P>,
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.public_paths = PublicPaths::parse(paths)?;
+        Ok(self)
+    }
+
+    /// Add a request pattern that doesn't require authentication
+    ///
+    /// Fails if `path` is not a valid pattern.
+    pub fn public_path<P>(mut self, path: P) -> Result<Self, PatternError>
+    where
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.public_paths.push(path)?;
+        Ok(self)
+    }
+
+    /// Set the realm reported in `WWW-Authenticate`
+    pub fn realm<S: Into<String>>(mut self, realm: S) -> Self {
+        self.realm = realm.into();
         self
     }
 
-    /// Check if path is public
-    fn is_public_path(&self, path: &str) -> bool {
-        self.public_paths.iter().any(|p| path.starts_with(p))
+    /// Build the 401 response for a rejected request
+    fn challenge(&self, err: &JwtError) -> Response {
+        // RFC 6750: no error code when credentials are simply missing
+        let header = match err {
+            JwtError::Missing => format!("Bearer realm=\"{}\"", self.realm),
+            err => format!(
+                "Bearer realm=\"{}\", error=\"invalid_token\", error_description=\"{}\"",
+                self.realm,
+                err.to_string().replace('"', "'")
+            ),
+        };
+        Response::unauthorized(err.to_string()).header("WWW-Authenticate", header)
     }
 }
 
+/// Extract the token from `Authorization: Bearer` credentials
+///
+/// The scheme is case-insensitive (RFC 7235).
+fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
 impl Middleware for AuthMiddleware {
-    fn before_request(
-        &self,
-        req: &mut Request,
-    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send>> {
-        let path = req.path().to_string();
-        let is_public = self.is_public_path(&path);
-        let auth_header = req.header("authorization").cloned();
-        
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        if self.public_paths.matches(req.method(), req.path()) {
+            return Box::pin(async { None });
+        }
+
+        let token = req.header("authorization").and_then(bearer_token);
+        let result = match token {
+            Some(token) => self.jwt.validate(token),
+            None => Err(JwtError::Missing),
+        };
+
+        let rejection = match result {
+            Ok(claims) => {
+                if let Some(subject) = claims.subject() {
+                    req.extensions_mut().insert(Principal::new(subject, "Bearer"));
+                }
+                req.extensions_mut().insert(claims);
+                None
+            }
+            Err(err) => {
+                log::debug!("Rejected {} {}: {}", req.method(), req.path(), err);
+                Some(self.challenge(&err))
+            }
+        };
+        Box::pin(async move { rejection })
+    }
+
+    fn name(&self) -> &str {
+        "Authentication"
+    }
+}
+
+/// Authenticated caller, available from `Request::principal`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// User name, token subject or API key owner
+    name: String,
+    /// Authentication scheme that produced it: `Bearer`, `Basic` or `ApiKey`
+    scheme: &'static str,
+}
+
+impl Principal {
+    /// Create a principal authenticated with the given scheme
+    pub fn new<S: Into<String>>(name: S, scheme: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            scheme,
+        }
+    }
+
+    /// Get the principal name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the authentication scheme
+    pub fn scheme(&self) -> &str {
+        self.scheme
+    }
+}
+
+/// Async check of a user name and password
+pub type BasicVerifierFn =
+    Arc<dyn Fn(String, String) -> Pin<Box<dyn Future<Output = bool> + Send>> + Send + Sync>;
+
+/// HTTP Basic authentication middleware
+///
+/// Checks `Authorization: Basic` credentials with an async verifier on every
+/// non-public path and attaches the user name as the request's `Principal`.
+/// Public paths follow the same rules as `AuthMiddleware`.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::middleware::BasicAuthMiddleware;
+///
+/// let basic = BasicAuthMiddleware::new(|user, password| {
+///     Box::pin(async move { user == "admin" && password == "hunter2" })
+/// })
+/// .realm("Admin area");
+/// ```
+pub struct BasicAuthMiddleware {
+    /// Requests that don't require authentication
+    public_paths: PublicPaths,
+    /// Credential check
+    verifier: BasicVerifierFn,
+    /// Realm reported in `WWW-Authenticate`
+    realm: String,
+}
+
+impl BasicAuthMiddleware {
+    /// Create a Basic auth middleware with an async credential verifier
+    pub fn new<F>(verifier: F) -> Self
+    where
+        F: Fn(String, String) -> Pin<Box<dyn Future<Output = bool> + Send>> + Send + Sync + 'static,
+    {
+        Self {
+            public_paths: PublicPaths::default(),
+            verifier: Arc::new(verifier),
+            realm: "dia".to_string(),
+        }
+    }
+
+    /// Set the requests that don't require authentication
+    ///
+    /// See `PublicPaths::parse`.
+    pub fn public_paths<I, P>(mut self, paths: I) -> Result<Self, PatternError>
+    where
+        I: IntoIterator<Item = P>,
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.public_paths = PublicPaths::parse(paths)?;
+        Ok(self)
+    }
+
+    /// Add a request pattern that doesn't require authentication
+    ///
+    /// Fails if `path` is not a valid pattern.
+    pub fn public_path<P>(mut self, path: P) -> Result<Self, PatternError>
+    where
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.public_paths.push(path)?;
+        Ok(self)
+    }
+
+    /// Set the realm reported in `WWW-Authenticate`
+    pub fn realm<S: Into<String>>(mut self, realm: S) -> Self {
+        self.realm = realm.into();
+        self
+    }
+
+    /// Build the 401 response asking for credentials
+    fn challenge(&self, message: &str) -> Response {
+        Response::unauthorized(message).header(
+            "WWW-Authenticate",
+            format!("Basic realm=\"{}\", charset=\"UTF-8\"", self.realm.replace('"', "'")),
+        )
+    }
+}
+
+/// Decode `Authorization: Basic` credentials into user name and password
+fn basic_credentials(header: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = header.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = BASE64.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+impl Middleware for BasicAuthMiddleware {
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
         Box::pin(async move {
-            if is_public {
+            if self.public_paths.matches(req.method(), req.path()) {
                 return None;
             }
+            let Some((user, password)) = req.header("authorization").and_then(basic_credentials) else {
+                return Some(self.challenge("Authentication required"));
+            };
 
-            // Check for Authorization header
-            if let Some(auth_header) = auth_header {
-                if auth_header.starts_with("Bearer ") {
-                    // TODO: Validate JWT token
-                    log::debug!("JWT token validation (not implemented)");
-                    return None;
-                }
+            if (self.verifier)(user.clone(), password).await {
+                req.extensions_mut().insert(Principal::new(user, "Basic"));
+                None
+            } else {
+                log::debug!("Rejected Basic credentials for '{}' on {} {}", user, req.method(), req.path());
+                Some(self.challenge("Invalid credentials"))
+            }
+        })
+    }
+
+    fn name(&self) -> &str {
+        "BasicAuth"
+    }
+}
+
+/// Async lookup of the principal owning an API key
+pub type ApiKeyVerifierFn =
+    Arc<dyn Fn(String) -> Pin<Box<dyn Future<Output = Option<String>> + Send>> + Send + Sync>;
+
+/// Where ApiKeyMiddleware looks for the key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiKeySource {
+    /// Request header, such as `X-API-Key`
+    Header(String),
+    /// Query parameter, such as `api_key`
+    ///
+    /// Keys in URLs end up in browser history and proxy logs; `AccessLog`
+    /// redacts the parameter, but prefer a header where clients allow it.
+    Query(String),
+    /// Cookie
+    Cookie(String),
+}
+
+/// API key authentication middleware
+///
+/// Reads a key from the configured sources (the `X-API-Key` header by
+/// default) on every non-public path and verifies it against a static set of
+/// named keys or an async callback. The key owner becomes the request's
+/// `Principal`. Public paths follow the same rules as `AuthMiddleware`.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::middleware::{ApiKeyMiddleware, ApiKeySource};
+///
+/// // Static keys, named after their owner
+/// let api_keys = ApiKeyMiddleware::new()
+///     .key("billing-service", "k_live_1234")
+///     .source(ApiKeySource::Header("X-API-Key".to_string()))
+///     .source(ApiKeySource::Query("api_key".to_string()));
+///
+/// // Keys looked up elsewhere
+/// let api_keys = ApiKeyMiddleware::with_verifier(|key| {
+///     Box::pin(async move { (key == "k_test").then(|| "tester".to_string()) })
+/// });
+/// ```
+pub struct ApiKeyMiddleware {
+    /// Requests that don't require authentication
+    public_paths: PublicPaths,
+    /// Places to read the key from, in order
+    sources: Vec<ApiKeySource>,
+    /// Owners of the static keys, with the SHA-256 digest of their key
+    keys: Vec<(String, [u8; 32])>,
+    /// Key lookup, used instead of `keys` when set
+    verifier: Option<ApiKeyVerifierFn>,
+}
+
+impl ApiKeyMiddleware {
+    /// Create an API key middleware accepting the keys added with `key`
+    pub fn new() -> Self {
+        Self {
+            public_paths: PublicPaths::default(),
+            sources: Vec::new(),
+            keys: Vec::new(),
+            verifier: None,
+        }
+    }
+
+    /// Create an API key middleware verifying keys with an async callback
+    ///
+    /// The callback returns the key owner, or `None` to reject the key.
+    pub fn with_verifier<F>(verifier: F) -> Self
+    where
+        F: Fn(String) -> Pin<Box<dyn Future<Output = Option<String>> + Send>> + Send + Sync + 'static,
+    {
+        Self {
+            verifier: Some(Arc::new(verifier)),
+            ..Self::new()
+        }
+    }
+
+    /// Accept a static key on behalf of a named owner
+    pub fn key<N: Into<String>, K: Into<String>>(mut self, owner: N, key: K) -> Self {
+        self.keys.push((owner.into(), Sha256::digest(key.into().as_bytes()).into()));
+        self
+    }
+
+    /// Add a place to read the key from
+    ///
+    /// Sources are tried in order. Without any, the `X-API-Key` header is used.
+    pub fn source(mut self, source: ApiKeySource) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Set the requests that don't require authentication
+    ///
+    /// See `PublicPaths::parse`.
+    pub fn public_paths<I, P>(mut self, paths: I) -> Result<Self, PatternError>
+    where
+        I: IntoIterator<Item = P>,
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.public_paths = PublicPaths::parse(paths)?;
+        Ok(self)
+    }
+
+    /// Add a request pattern that doesn't require authentication
+    ///
+    /// Fails if `path` is not a valid pattern.
+    pub fn public_path<P>(mut self, path: P) -> Result<Self, PatternError>
+    where
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.public_paths.push(path)?;
+        Ok(self)
+    }
+
+    /// Read the key from the first source that has one
+    fn find_key(&self, req: &Request) -> Option<String> {
+        let default = [ApiKeySource::Header("X-API-Key".to_string())];
+        let sources = if self.sources.is_empty() { &default[..] } else { &self.sources[..] };
+        sources
+            .iter()
+            .find_map(|source| match source {
+                ApiKeySource::Header(name) => req.header(name).map(str::to_string),
+                ApiKeySource::Query(name) => req.query(name).cloned(),
+                ApiKeySource::Cookie(name) => req.cookie(name).map(|cookie| cookie.value().to_string()),
+            })
+            .filter(|key| !key.is_empty())
+    }
+
+    /// Find the owner of a static key, comparing in constant time
+    ///
+    /// Digests are compared rather than the keys, so the time taken does not
+    /// depend on the length of the configured keys either.
+    fn static_owner(&self, key: &str) -> Option<String> {
+        let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+        self.keys
+            .iter()
+            .fold(None, |found, (owner, candidate)| {
+                let matched = constant_time_eq(candidate, &digest);
+                found.or(matched.then(|| owner.clone()))
+            })
+    }
+}
+
+impl Default for ApiKeyMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compare two digests without short-circuiting on the first difference
+fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl Middleware for ApiKeyMiddleware {
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        for source in &self.sources {
+            if let ApiKeySource::Query(name) = source {
+                SensitiveQuery::mark(req, name);
+            }
+        }
+
+        Box::pin(async move {
+            if self.public_paths.matches(req.method(), req.path()) {
+                return None;
             }
+            let Some(key) = self.find_key(req) else {
+                return Some(Response::unauthorized("API key required"));
+            };
 
-            // Return unauthorized response
-            Some(Response::unauthorized("Authentication required"))
+            let owner = match &self.verifier {
+                Some(verifier) => verifier(key).await,
+                None => self.static_owner(&key),
+            };
+            match owner {
+                Some(owner) => {
+                    req.extensions_mut().insert(Principal::new(owner, "ApiKey"));
+                    None
+                }
+                None => {
+                    log::debug!("Rejected API key on {} {}", req.method(), req.path());
+                    Some(Response::unauthorized("Invalid API key"))
+                }
+            }
         })
     }
 
     fn name(&self) -> &str {
-        "Authentication"
+        "ApiKey"
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Run a request through a middleware without credentials
+    fn status<M: Middleware + 'static>(auth: M, method: &str, path: &str) -> u16 {
+        let chain = MiddlewareChain::new(vec![Box::new(auth)]);
+        let req = TestRequest::default()
+            .method(method.parse().unwrap())
+            .uri(path)
+            .to_http_request();
+        let handler: HandlerFn = Arc::new(|_req, _resp| Box::pin(async { Response::ok_text("ok") }));
+        futures::executor::block_on(chain.handle(Request::new(req), handler)).get_status()
+    }
+
+    /// Middleware recording its hooks, optionally short-circuiting
+    struct Record {
+        name: &'static str,
+        stop: bool,
+        calls: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Middleware for Record {
+        fn before_request<'a>(
+            &'a self,
+            _req: &'a mut Request,
+        ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+            self.calls.lock().unwrap().push(format!("before {}", self.name));
+            let stop = self.stop;
+            Box::pin(async move { stop.then(|| Response::new().status(403)) })
+        }
+
+        fn after_request<'a>(
+            &'a self,
+            _req: &'a Request,
+            resp: Response,
+        ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
+            self.calls.lock().unwrap().push(format!("after {}", self.name));
+            Box::pin(async move { resp })
+        }
+    }
+
+    /// Run a request through `Record` middlewares, returning the status and
+    /// the hooks called
+    fn record(middlewares: &[(&'static str, bool)]) -> (u16, Vec<String>) {
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let chain = MiddlewareChain::new(
+            middlewares
+                .iter()
+                .map(|&(name, stop)| Box::new(Record { name, stop, calls: calls.clone() }) as Box<dyn Middleware>)
+                .collect(),
+        );
+        let handler_calls = calls.clone();
+        let handler: HandlerFn = Arc::new(move |_req, _resp| {
+            handler_calls.lock().unwrap().push("handler".to_string());
+            Box::pin(async { Response::ok_text("ok") })
+        });
+        let req = Request::new(TestRequest::get().uri("/").to_http_request());
+        let status = futures::executor::block_on(chain.handle(req, handler)).get_status();
+        let calls = calls.lock().unwrap().clone();
+        (status, calls)
+    }
+
+    #[test]
+    fn chain_runs_after_hooks_in_reverse() {
+        let (status, calls) = record(&[("outer", false), ("inner", false)]);
+        assert_eq!(status, 200);
+        assert_eq!(calls, ["before outer", "before inner", "handler", "after inner", "after outer"]);
+    }
+
+    #[test]
+    fn short_circuits_skip_inner_middlewares_and_the_handler() {
+        let (status, calls) = record(&[("outer", false), ("guard", true), ("inner", false)]);
+        assert_eq!(status, 403);
+        assert_eq!(calls, ["before outer", "before guard", "after outer"]);
+    }
+
+    #[test]
+    fn default_only_exposes_health() {
+        assert_eq!(status(AuthMiddleware::new("secret"), "GET", "/health"), 200);
+        assert_eq!(status(AuthMiddleware::new("secret"), "GET", "/"), 401);
+        assert_eq!(status(AuthMiddleware::new("secret"), "GET", "/admin"), 401);
+        assert_eq!(status(AuthMiddleware::new("secret"), "GET", "/healthz"), 401);
+    }
+
+    #[test]
+    fn root_does_not_expose_admin() {
+        let auth = || AuthMiddleware::new("secret").public_paths(["/"]).unwrap();
+        assert_eq!(status(auth(), "GET", "/"), 200);
+        assert_eq!(status(auth(), "GET", "/admin"), 401);
+        assert_eq!(status(auth(), "GET", "/admin/users"), 401);
+    }
+
+    #[test]
+    fn globs_and_methods() {
+        let auth = || AuthMiddleware::new("secret").public_paths(["GET /docs/**", "/users/*"]).unwrap();
+        assert_eq!(status(auth(), "GET", "/docs/guide/intro"), 200);
+        assert_eq!(status(auth(), "POST", "/docs/guide/intro"), 401);
+        assert_eq!(status(auth(), "GET", "/users/42"), 200);
+        assert_eq!(status(auth(), "GET", "/users/42/settings"), 401);
+    }
+
+    /// Run a request carrying an `Authorization` header through AuthMiddleware
+    fn authenticate(auth: AuthMiddleware, authorization: &str) -> Response {
+        call(auth, TestRequest::get().uri("/me").insert_header(("authorization", authorization)))
+    }
+
+    /// Run a request through a middleware, answering with the claims'
+    /// subject and the principal
+    fn call<M: Middleware + 'static>(middleware: M, req: TestRequest) -> Response {
+        let chain = MiddlewareChain::new(vec![Box::new(middleware)]);
+        let handler: HandlerFn = Arc::new(|req, _resp| {
+            Box::pin(async move {
+                let subject = req.claims().and_then(|claims| claims.subject()).unwrap_or("-");
+                let principal = req.principal().map(|p| format!("{} {}", p.scheme(), p.name()));
+                Response::ok_text(format!("{} {}", subject, principal.unwrap_or_default()))
+            })
+        });
+        futures::executor::block_on(chain.handle(Request::new(req.to_http_request()), handler))
+    }
+
+    fn body_text(resp: &Response) -> &str {
+        match resp.get_body() {
+            ResponseBody::Text(text) => text,
+            body => panic!("unexpected body {:?}", body),
+        }
+    }
+
+    #[test]
+    fn bearer_tokens_attach_claims_and_principal() {
+        let jwt = JwtConfig::hmac("secret");
+        let exp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 60;
+        let token = jwt.sign(&serde_json::json!({ "sub": "alice", "exp": exp })).unwrap();
+
+        for scheme in ["Bearer", "bearer", "BEARER"] {
+            let resp = authenticate(AuthMiddleware::new("secret"), &format!("{} {}", scheme, token));
+            assert_eq!(resp.get_status(), 200);
+            assert_eq!(body_text(&resp), "alice Bearer alice");
+        }
+    }
+
+    #[test]
+    fn rejected_tokens_get_a_challenge() {
+        let exp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() - 120;
+        let expired = JwtConfig::hmac("secret").sign(&serde_json::json!({ "sub": "alice", "exp": exp })).unwrap();
+        let forged = JwtConfig::hmac("other").sign(&serde_json::json!({ "sub": "alice", "exp": exp + 240 })).unwrap();
+
+        let resp = authenticate(AuthMiddleware::new("secret").realm("api"), &format!("Bearer {}", expired));
+        assert_eq!(resp.get_status(), 401);
+        assert_eq!(
+            resp.get_header("www-authenticate"),
+            Some("Bearer realm=\"api\", error=\"invalid_token\", error_description=\"Token expired\"")
+        );
+
+        let resp = authenticate(AuthMiddleware::new("secret"), &format!("Bearer {}", forged));
+        assert_eq!(resp.get_status(), 401);
+        assert!(resp.get_header("www-authenticate").unwrap().contains("Invalid token signature"));
+
+        let resp = authenticate(AuthMiddleware::new("secret"), "Basic YWxpY2U6cHc=");
+        assert_eq!(resp.get_status(), 401);
+        assert_eq!(resp.get_header("www-authenticate"), Some("Bearer realm=\"dia\""));
+    }
+
+    #[test]
+    fn basic_auth_checks_credentials() {
+        let basic = || {
+            BasicAuthMiddleware::new(|user, password| {
+                Box::pin(async move { user == "alice" && password == "hunter2" })
+            })
+            .realm("Admin \"area\"")
+        };
+        let login = |credentials: &str| {
+            let header = format!("basic {}", BASE64.encode(credentials));
+            TestRequest::get().uri("/admin").insert_header(("authorization", header))
+        };
+
+        let resp = call(basic(), login("alice:hunter2"));
+        assert_eq!(resp.get_status(), 200);
+        assert_eq!(body_text(&resp), "- Basic alice");
+
+        for resp in [call(basic(), login("alice:wrong")), call(basic(), TestRequest::get().uri("/admin"))] {
+            assert_eq!(resp.get_status(), 401);
+            assert_eq!(
+                resp.get_header("www-authenticate"),
+                Some("Basic realm=\"Admin 'area'\", charset=\"UTF-8\"")
+            );
+        }
+    }
+
+    #[test]
+    fn api_keys_identify_their_owner() {
+        let api_keys = || {
+            ApiKeyMiddleware::new()
+                .key("billing", "k_live_1234")
+                .source(ApiKeySource::Header("X-API-Key".to_string()))
+                .source(ApiKeySource::Query("api_key".to_string()))
+        };
+
+        let resp = call(api_keys(), TestRequest::get().uri("/invoices").insert_header(("x-api-key", "k_live_1234")));
+        assert_eq!(resp.get_status(), 200);
+        assert_eq!(body_text(&resp), "- ApiKey billing");
+
+        let resp = call(api_keys(), TestRequest::get().uri("/invoices?api_key=k_live_1234"));
+        assert_eq!(body_text(&resp), "- ApiKey billing");
+
+        let resp = call(api_keys(), TestRequest::get().uri("/invoices?api_key=k_live_12345"));
+        assert_eq!(resp.get_status(), 401);
+        assert_eq!(call(api_keys(), TestRequest::get().uri("/invoices")).get_status(), 401);
+
+        let verified = ApiKeyMiddleware::with_verifier(|key| {
+            Box::pin(async move { (key == "k_test").then(|| "tester".to_string()) })
+        });
+        let resp = call(verified, TestRequest::get().uri("/").insert_header(("x-api-key", "k_test")));
+        assert_eq!(body_text(&resp), "- ApiKey tester");
+    }
+
+    #[test]
+    fn basic_and_api_key_share_public_path_rules() {
+        let basic = || {
+            BasicAuthMiddleware::new(|_, _| Box::pin(async { true })).public_paths(["/", "GET /docs/**"]).unwrap()
+        };
+        assert_eq!(status(basic(), "GET", "/"), 200);
+        assert_eq!(status(basic(), "GET", "/admin"), 401);
+        assert_eq!(status(basic(), "POST", "/docs/intro"), 401);
+
+        let api_key = || ApiKeyMiddleware::new().public_paths(["/", "GET /docs/**"]).unwrap();
+        assert_eq!(status(api_key(), "GET", "/docs/intro"), 200);
+        assert_eq!(status(api_key(), "GET", "/admin"), 401);
+    }
+
+    #[test]
+    fn public_paths_report_invalid_patterns() {
+        assert!(AuthMiddleware::new("secret").public_paths(["/", "docs"]).is_err());
+        assert!(AuthMiddleware::new("secret").public_path("GET /docs/**").is_ok());
+        let parsed = PathPattern::new("/status");
+        assert_eq!(status(AuthMiddleware::new("secret").public_path(parsed).unwrap(), "GET", "/status"), 200);
+    }
+
+    #[test]
+    fn cors_matches_wildcard_subdomains() {
+        let cors = CorsMiddleware::new().allowed_origins(vec![
+            "https://app.example.com".to_string(),
+            "https://*.example.dev".to_string(),
+        ]);
+        assert!(cors.is_allowed_origin("https://app.example.com"));
+        assert!(cors.is_allowed_origin("https://a.b.example.dev"));
+        assert!(!cors.is_allowed_origin("https://example.dev"));
+        assert!(!cors.is_allowed_origin("http://a.example.dev"));
+        assert!(!cors.is_allowed_origin("https://evil.com/.example.dev"));
+        assert!(!cors.is_allowed_origin("https://app.example.com.evil.com"));
+    }
+
+    #[test]
+    fn cors_refuses_any_origin_with_credentials() {
+        let origins = || vec!["https://app.example.com".to_string()];
+        let chain = |cors: CorsMiddleware| MiddlewareChain::new(vec![Box::new(cors)]).validate();
+
+        // Reported whichever order the builder methods are called in
+        let err = chain(CorsMiddleware::new().allow_credentials(true)).unwrap_err();
+        assert_eq!(err.middleware, "CORS");
+        assert!(chain(CorsMiddleware::new().allowed_origins(origins()).allow_credentials(true)).is_ok());
+        assert!(chain(CorsMiddleware::new().allow_credentials(true).allowed_origins(origins())).is_ok());
+        let any = CorsMiddleware::new().allow_credentials(true).allowed_origins(vec!["*".to_string()]);
+        assert!(chain(any).is_err());
+    }
+
+    #[test]
+    fn cors_preflights_are_answered_once() {
+        let cors = || CorsMiddleware::new().allowed_origins(vec!["https://app.example.com".to_string()]);
+        let preflight = TestRequest::default()
+            .method(actix_web::http::Method::OPTIONS)
+            .uri("/items")
+            .insert_header(("origin", "https://app.example.com"))
+            .insert_header(("access-control-request-method", "POST"));
+        let resp = call(cors(), preflight);
+        assert_eq!(resp.get_status(), 204);
+        assert_eq!(resp.get_header("access-control-allow-methods"), Some("GET, POST, PUT, DELETE"));
+        assert_eq!(resp.get_headers().get_all("vary").len(), 1);
+
+        // A handler setting Allow-Methods itself still gets the CORS headers
+        let chain = MiddlewareChain::new(vec![Box::new(cors())]);
+        let handler: HandlerFn = Arc::new(|_req, _resp| {
+            Box::pin(async { Response::ok_text("ok").header("Access-Control-Allow-Methods", "GET") })
+        });
+        let req = TestRequest::get().uri("/items").insert_header(("origin", "https://app.example.com"));
+        let resp = futures::executor::block_on(chain.handle(Request::new(req.to_http_request()), handler));
+        assert_eq!(resp.get_header("access-control-allow-origin"), Some("https://app.example.com"));
+    }
+}
@@ -0,0 +1,319 @@
+//! Multipart module for dia framework
+//!
+//! Provides parsing of `multipart/form-data` request bodies into fields and
+//! files, spilling large parts to temporary files.
+
+use crate::request::{BodyConfig, BodyError};
+use actix_web::web::{self, Bytes, BytesMut};
+use futures::StreamExt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+/// Amount of data buffered before each write to a spilled part's file
+const WRITE_SIZE: usize = 64 * 1024;
+
+/// A parsed `multipart/form-data` body
+#[derive(Debug, Clone, Default)]
+pub struct Multipart {
+    /// Parts in the order they were sent
+    parts: Vec<Part>,
+}
+
+impl Multipart {
+    /// Iterate over all parts in order
+    pub fn iter(&self) -> impl Iterator<Item = &Part> {
+        self.parts.iter()
+    }
+
+    /// Iterate over the plain form fields (parts without a filename)
+    pub fn fields(&self) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(|part| !part.is_file())
+    }
+
+    /// Iterate over the uploaded files (parts with a filename)
+    pub fn files(&self) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(|part| part.is_file())
+    }
+
+    /// Get the first part with the given field name
+    pub fn get(&self, name: &str) -> Option<&Part> {
+        self.parts.iter().find(|part| part.name == name)
+    }
+
+    /// Get the text value of the first plain field with the given name
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields()
+            .find(|part| part.name == name)
+            .and_then(|part| match &part.data {
+                PartData::Memory(bytes) => std::str::from_utf8(bytes).ok(),
+                PartData::File(_) => None,
+            })
+    }
+
+    /// Number of parts
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Check if the body had no parts
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
+
+/// A single field or file of a multipart body
+#[derive(Debug, Clone)]
+pub struct Part {
+    /// Form field name
+    name: String,
+    /// Client-side file name, for file uploads
+    filename: Option<String>,
+    /// Declared content type of the part
+    content_type: Option<String>,
+    /// Part contents
+    data: PartData,
+    /// Size in bytes
+    len: usize,
+}
+
+/// Storage of a part's contents
+#[derive(Debug, Clone)]
+pub enum PartData {
+    /// Small parts are kept in memory
+    Memory(Bytes),
+    /// Large parts are spilled to a temporary file, deleted when dropped
+    File(Arc<NamedTempFile>),
+}
+
+impl Part {
+    /// Form field name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Client-side file name, if this part is a file upload
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Declared content type of the part
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Check if this part is a file upload
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// Size of the part in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the part is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the part storage
+    pub fn data(&self) -> &PartData {
+        &self.data
+    }
+
+    /// Path of the temporary file, if the part was spilled to disk
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            PartData::Memory(_) => None,
+            PartData::File(file) => Some(file.path()),
+        }
+    }
+
+    /// Get the part contents, reading them back from disk if spilled
+    pub async fn bytes(&self) -> io::Result<Bytes> {
+        match &self.data {
+            PartData::Memory(bytes) => Ok(bytes.clone()),
+            PartData::File(file) => tokio::fs::read(file.path()).await.map(Bytes::from),
+        }
+    }
+
+    /// Get the part contents as UTF-8 text
+    pub async fn text(&self) -> Result<String, BodyError> {
+        let bytes = self.bytes().await.map_err(|e| BodyError::Payload(e.to_string()))?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| BodyError::InvalidUtf8)
+    }
+}
+
+/// Read a multipart payload, enforcing the configured limits
+pub(crate) async fn read(
+    headers: &actix_web::http::header::HeaderMap,
+    payload: web::Payload,
+    config: &BodyConfig,
+) -> Result<Multipart, BodyError> {
+    let mut multipart = actix_multipart::Multipart::new(headers, payload);
+    let mut parts = Vec::new();
+    let mut total = 0usize;
+
+    while let Some(field) = multipart.next().await {
+        let mut field = field.map_err(|e| BodyError::Payload(e.to_string()))?;
+        let disposition = field.content_disposition().cloned();
+        let name = disposition
+            .as_ref()
+            .and_then(|d| d.get_name())
+            .unwrap_or_default()
+            .to_string();
+        let filename = disposition
+            .as_ref()
+            .and_then(|d| d.get_filename())
+            .map(str::to_string);
+        let content_type = field.content_type().map(|mime| mime.to_string());
+
+        let mut buffer = BytesMut::new();
+        let mut spill: Option<NamedTempFile> = None;
+        let mut len = 0usize;
+
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| BodyError::Payload(e.to_string()))?;
+            len += chunk.len();
+            total += chunk.len();
+            if len > config.max_part_size {
+                return Err(BodyError::TooLarge { limit: config.max_part_size });
+            }
+            if total > config.max_multipart_size {
+                return Err(BodyError::TooLarge { limit: config.max_multipart_size });
+            }
+
+            buffer.extend_from_slice(&chunk);
+            if spill.is_none() && buffer.len() > config.part_memory_limit {
+                spill = Some(blocking(NamedTempFile::new).await?);
+            }
+            if buffer.len() >= WRITE_SIZE {
+                if let Some(file) = spill.take() {
+                    let data = buffer.split().freeze();
+                    spill = Some(blocking(move || write(file, &data)).await?);
+                }
+            }
+        }
+
+        let data = match spill {
+            Some(file) => {
+                let data = buffer.freeze();
+                let file = blocking(move || {
+                    let mut file = write(file, &data)?;
+                    file.flush()?;
+                    Ok(file)
+                })
+                .await?;
+                PartData::File(Arc::new(file))
+            }
+            None => PartData::Memory(buffer.freeze()),
+        };
+        parts.push(Part {
+            name,
+            filename,
+            content_type,
+            data,
+            len,
+        });
+    }
+
+    Ok(Multipart { parts })
+}
+
+/// Append data to a spilled part's file
+fn write(mut file: NamedTempFile, data: &[u8]) -> io::Result<NamedTempFile> {
+    file.write_all(data)?;
+    Ok(file)
+}
+
+/// Run blocking file I/O on the blocking thread pool
+async fn blocking<T, F>(f: F) -> Result<T, BodyError>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match web::block(f).await {
+        Ok(result) => result.map_err(|e| BodyError::Payload(e.to_string())),
+        Err(err) => Err(BodyError::Payload(err.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::middleware::{AuthMiddleware, MiddlewareChain};
+    use crate::request::BodyConfig;
+    use crate::{BasicController, Controller, Response};
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App};
+
+    /// Build a multipart body with one file part
+    fn upload(data: &[u8]) -> Vec<u8> {
+        let mut body = b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\r\n".to_vec();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n--XyZ--\r\n");
+        body
+    }
+
+    fn controller() -> BasicController {
+        BasicController::new().post("/upload", |req, _resp| {
+            Box::pin(async move {
+                let multipart = req.multipart().unwrap();
+                let part = multipart.get("file").unwrap();
+                let spilled = part.path().is_some();
+                let len = part.bytes().await.unwrap().len();
+                Response::ok_text(format!("{} {}", len, spilled))
+            })
+        })
+    }
+
+    #[actix_web::test]
+    async fn large_parts_spill_to_disk() {
+        let config = BodyConfig {
+            part_memory_limit: 1024,
+            ..BodyConfig::default()
+        };
+        let controller = controller();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .configure(|cfg| controller.register_routes(cfg)),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/upload")
+            .insert_header(("Content-Type", "Multipart/Form-Data; boundary=XyZ"))
+            .set_payload(upload(&[7; 100_000]))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(read_body(resp).await, "100000 true");
+    }
+
+    #[actix_web::test]
+    async fn middlewares_reject_before_the_upload_is_read() {
+        let config = BodyConfig {
+            max_multipart_size: 1024,
+            ..BodyConfig::default()
+        };
+        let chain = MiddlewareChain::new(vec![Box::new(AuthMiddleware::new("secret"))]);
+        let controller = controller();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(chain))
+                .configure(|cfg| controller.register_routes(cfg)),
+        )
+        .await;
+
+        // Too large, but refused as unauthenticated before it is read
+        let req = TestRequest::post()
+            .uri("/upload")
+            .insert_header(("Content-Type", "multipart/form-data; boundary=XyZ"))
+            .set_payload(upload(&[7; 4096]))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 401);
+    }
+}
@@ -0,0 +1,300 @@
+//! Path pattern module for dia framework
+//!
+//! Provides the PathPattern rules, and the PublicPaths list the
+//! authentication middlewares use to decide which requests are public.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// A request rule made of an optional method list and a path glob
+///
+/// Written as `[METHODS] PATH`:
+///
+/// - `/health` matches that exact path (a trailing slash is ignored)
+/// - `/users/*` matches exactly one segment, like `/users/42`
+/// - `/docs/**` matches zero or more segments: `/docs`, `/docs/a/b`
+/// - `GET /docs/**` or `GET,HEAD /docs/**` only match those methods
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::PathPattern;
+///
+/// let pattern: PathPattern = "GET /docs/**".parse().unwrap();
+/// assert!(pattern.matches("GET", "/docs/intro"));
+/// assert!(!pattern.matches("POST", "/docs/intro"));
+///
+/// let root = PathPattern::new("/");
+/// assert!(root.matches("GET", "/"));
+/// assert!(!root.matches("GET", "/admin"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    /// Allowed methods, or `None` for any method
+    methods: Option<Vec<String>>,
+    /// Path segments to match
+    segments: Vec<Segment>,
+}
+
+/// One segment of a path pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// Literal segment
+    Exact(String),
+    /// `*`: any single segment
+    Any,
+    /// `**`: any number of segments, including none
+    Rest,
+}
+
+/// Error returned when a pattern cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid path pattern '{0}': expected '[METHODS] /path'")]
+pub struct PatternError(String);
+
+impl PathPattern {
+    /// Parse a pattern
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern is invalid; use `str::parse` to handle the error.
+    pub fn new(pattern: &str) -> Self {
+        match pattern.parse() {
+            Ok(pattern) => pattern,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Check if a request matches the pattern
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        if let Some(methods) = &self.methods {
+            if !methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method)) {
+                return false;
+            }
+        }
+        let path: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+        match_segments(&self.segments, &path)
+    }
+}
+
+/// Match pattern segments against path segments, backtracking on `**`
+fn match_segments(pattern: &[Segment], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((Segment::Rest, rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((first, tail)) => {
+                let matched = match segment {
+                    Segment::Exact(literal) => literal == first,
+                    _ => true,
+                };
+                matched && match_segments(rest, tail)
+            }
+            None => false,
+        },
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = PatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let invalid = || PatternError(pattern.to_string());
+        let (methods, path) = match pattern.trim().split_once(char::is_whitespace) {
+            Some((methods, path)) => {
+                let methods: Vec<String> = methods.split(',').map(|m| m.trim().to_ascii_uppercase()).collect();
+                if methods.iter().any(|m| m.is_empty() || !m.chars().all(|c| c.is_ascii_alphabetic())) {
+                    return Err(invalid());
+                }
+                (Some(methods), path.trim())
+            }
+            None => (None, pattern.trim()),
+        };
+        if !path.starts_with('/') {
+            return Err(invalid());
+        }
+
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| match segment {
+                "*" => Segment::Any,
+                "**" => Segment::Rest,
+                literal => Segment::Exact(literal.to_string()),
+            })
+            .collect();
+        Ok(Self { methods, segments })
+    }
+}
+
+impl TryFrom<&str> for PathPattern {
+    type Error = PatternError;
+
+    fn try_from(pattern: &str) -> Result<Self, Self::Error> {
+        pattern.parse()
+    }
+}
+
+impl TryFrom<String> for PathPattern {
+    type Error = PatternError;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        pattern.parse()
+    }
+}
+
+/// Lets APIs taking `TryInto<PathPattern>` accept already parsed patterns
+impl From<Infallible> for PatternError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+/// Requests the authentication middlewares let through without credentials
+///
+/// The default only makes `/health` public.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::PublicPaths;
+///
+/// let public = PublicPaths::parse(["/", "GET /docs/**"]).unwrap();
+/// assert!(public.matches("GET", "/docs/intro"));
+/// assert!(!public.matches("GET", "/health"));
+/// assert!(PublicPaths::default().matches("GET", "/health"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicPaths(Vec<PathPattern>);
+
+impl PublicPaths {
+    /// Create an empty list, making every request require credentials
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Parse a list of patterns
+    ///
+    /// Accepts pattern strings or parsed `PathPattern`s, and fails if a
+    /// string is not a valid pattern.
+    pub fn parse<I, P>(paths: I) -> Result<Self, PatternError>
+    where
+        I: IntoIterator<Item = P>,
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        let mut public = Self::new();
+        for path in paths {
+            public.push(path)?;
+        }
+        Ok(public)
+    }
+
+    /// Add a pattern, failing if it is not valid
+    pub fn push<P>(&mut self, path: P) -> Result<(), PatternError>
+    where
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.0.push(path.try_into().map_err(Into::into)?);
+        Ok(())
+    }
+
+    /// Check if a request matches any pattern
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        self.0.iter().any(|pattern| pattern.matches(method, path))
+    }
+}
+
+impl Default for PublicPaths {
+    fn default() -> Self {
+        Self(vec![PathPattern::new("/health")])
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(methods) = &self.methods {
+            write!(f, "{} ", methods.join(","))?;
+        }
+        if self.segments.is_empty() {
+            return f.write_str("/");
+        }
+        for segment in &self.segments {
+            match segment {
+                Segment::Exact(literal) => write!(f, "/{}", literal)?,
+                Segment::Any => f.write_str("/*")?,
+                Segment::Rest => f.write_str("/**")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, method: &str, path: &str) -> bool {
+        PathPattern::new(pattern).matches(method, path)
+    }
+
+    #[test]
+    fn root_only_matches_root() {
+        assert!(matches("/", "GET", "/"));
+        assert!(!matches("/", "GET", "/admin"));
+        assert!(!matches("/", "GET", "/admin/users"));
+    }
+
+    #[test]
+    fn exact_paths_do_not_match_prefixes() {
+        assert!(matches("/health", "GET", "/health"));
+        assert!(matches("/health", "GET", "/health/"));
+        assert!(!matches("/health", "GET", "/healthz"));
+        assert!(!matches("/health", "GET", "/health/details"));
+    }
+
+    #[test]
+    fn single_star_matches_one_segment() {
+        assert!(matches("/users/*", "GET", "/users/42"));
+        assert!(!matches("/users/*", "GET", "/users"));
+        assert!(!matches("/users/*", "GET", "/users/42/posts"));
+        assert!(matches("/users/*/avatar", "GET", "/users/42/avatar"));
+        assert!(!matches("/*", "GET", "/"));
+    }
+
+    #[test]
+    fn double_star_matches_any_depth() {
+        assert!(matches("/docs/**", "GET", "/docs"));
+        assert!(matches("/docs/**", "GET", "/docs/intro"));
+        assert!(matches("/docs/**", "GET", "/docs/a/b/c"));
+        assert!(!matches("/docs/**", "GET", "/documents"));
+        assert!(matches("/**/public", "GET", "/a/b/public"));
+        assert!(matches("/**", "GET", "/anything/at/all"));
+    }
+
+    #[test]
+    fn methods_restrict_matches() {
+        assert!(matches("GET /docs/**", "GET", "/docs/intro"));
+        assert!(matches("GET /docs/**", "get", "/docs/intro"));
+        assert!(!matches("GET /docs/**", "POST", "/docs/intro"));
+        assert!(matches("GET,HEAD /docs", "HEAD", "/docs"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!("docs".parse::<PathPattern>().is_err());
+        assert!("GET docs".parse::<PathPattern>().is_err());
+        assert!("G3T /docs".parse::<PathPattern>().is_err());
+        assert!(PathPattern::try_from("health".to_string()).is_err());
+        assert_eq!(PathPattern::try_from("GET /docs/**"), Ok(PathPattern::new("GET /docs/**")));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for pattern in ["/", "/health", "GET,HEAD /docs/**", "/users/*/avatar"] {
+            assert_eq!(PathPattern::new(pattern).to_string(), pattern);
+        }
+    }
+}
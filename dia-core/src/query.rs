@@ -0,0 +1,487 @@
+//! Query module for dia framework
+//!
+//! Provides the QueryParams struct, an ordered multi-valued view of a query
+//! string with support for flag-style and bracketed (`filter[status]`) keys.
+
+use serde::de::value::{Error as DeError, StrDeserializer};
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use std::str::FromStr;
+
+/// Parsed query string that keeps every key/value pair in order
+///
+/// Keys without `=` (`?debug`) are kept with an empty value, and `+` is
+/// decoded as a space as in HTML forms.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::query::QueryParams;
+///
+/// let query = QueryParams::parse("tag=a&tag=b&debug&q=hello+world&filter[status]=open");
+/// assert_eq!(query.get_all("tag"), vec!["a", "b"]);
+/// assert!(query.contains_key("debug"));
+/// assert_eq!(query.get("q").map(String::as_str), Some("hello world"));
+/// assert_eq!(query.nested("filter").get("status").map(String::as_str), Some("open"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryParams {
+    /// Decoded key/value pairs in their original order
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryParams {
+    /// Parse a raw (still percent-encoded) query string
+    pub fn parse(query: &str) -> Self {
+        let pairs = form_urlencoded::parse(query.as_bytes())
+            .filter(|(key, _)| !key.is_empty())
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        Self { pairs }
+    }
+
+    /// Build from already decoded key/value pairs
+    pub fn from_pairs<I, K, V>(pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            pairs: pairs.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+        }
+    }
+
+    /// Get the first value for a key
+    ///
+    /// Like `get_all`, values sent with the array-style `name[]` key count.
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.pairs.iter().find(|(key, _)| is_key(key, name)).map(|(_, value)| value)
+    }
+
+    /// Get every value for a key, in order
+    ///
+    /// Values sent with the array-style `name[]` key are included.
+    pub fn get_all(&self, name: &str) -> Vec<&String> {
+        self.pairs
+            .iter()
+            .filter(|(key, _)| is_key(key, name))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Check if a key is present, with or without a value
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.pairs.iter().any(|(key, _)| is_key(key, name))
+    }
+
+    /// Get the parameters nested under a bracketed key
+    ///
+    /// `filter[status]=open&filter[tags][]=x` nested under `filter` yields
+    /// `status=open&tags[]=x`.
+    pub fn nested(&self, name: &str) -> QueryParams {
+        let pairs = self
+            .pairs
+            .iter()
+            .filter_map(|(key, value)| {
+                let rest = key.strip_prefix(name)?.strip_prefix('[')?;
+                let close = rest.find(']')?;
+                let inner = format!("{}{}", &rest[..close], &rest[close + 1..]);
+                Some((inner, value.clone()))
+            })
+            .filter(|(key, _)| !key.is_empty())
+            .collect();
+        QueryParams { pairs }
+    }
+
+    /// Iterate over all key/value pairs in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Number of key/value pairs
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Check if there are no parameters
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Deserialize the parameters into `T`
+    ///
+    /// Bracketed keys become nested structs or maps, and repeated keys
+    /// become sequences. Scalar fields take the first value.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, serde_path_to_error::Error<DeError>> {
+        let root = Node::build(&self.pairs);
+        serde_path_to_error::deserialize(NodeDeserializer(&root))
+    }
+}
+
+/// Check if a key is `name` or its array-style form `name[]`
+fn is_key(key: &str, name: &str) -> bool {
+    key == name || key.strip_suffix("[]") == Some(name)
+}
+
+/// Split `a[b][c]` into `["a", "b", "c"]`, leaving malformed keys whole
+fn split_key(key: &str) -> Vec<&str> {
+    let Some(open) = key.find('[') else {
+        return vec![key];
+    };
+    if open == 0 || !key.ends_with(']') {
+        return vec![key];
+    }
+
+    let mut segments = vec![&key[..open]];
+    for part in key[open + 1..key.len() - 1].split("][") {
+        if part.contains('[') || part.contains(']') {
+            return vec![key];
+        }
+        segments.push(part);
+    }
+    segments
+}
+
+/// Tree of query values built from bracketed keys
+#[derive(Debug, Default)]
+struct Node {
+    /// Values assigned directly to this key
+    values: Vec<String>,
+    /// Nested keys in first-seen order
+    children: Vec<(String, Node)>,
+}
+
+impl Node {
+    fn build(pairs: &[(String, String)]) -> Self {
+        let mut root = Node::default();
+        for (key, value) in pairs {
+            let mut node = &mut root;
+            for segment in split_key(key) {
+                if segment.is_empty() {
+                    // `tag[]` appends to `tag`
+                    continue;
+                }
+                node = node.child(segment);
+            }
+            node.values.push(value.clone());
+        }
+        root
+    }
+
+    /// Nested nodes in index order when every key is an index (`a[1]`,
+    /// `a[0]`), otherwise in first-seen order
+    fn indexed_children(&self) -> Vec<&Node> {
+        let indexes: Option<Vec<usize>> = self.children.iter().map(|(key, _)| key.parse().ok()).collect();
+        let mut children: Vec<(usize, &Node)> = match indexes {
+            Some(indexes) => indexes.into_iter().zip(self.children.iter().map(|(_, node)| node)).collect(),
+            None => self.children.iter().map(|(_, node)| node).enumerate().collect(),
+        };
+        children.sort_by_key(|(index, _)| *index);
+        children.into_iter().map(|(_, node)| node).collect()
+    }
+
+    fn child(&mut self, name: &str) -> &mut Node {
+        let index = match self.children.iter().position(|(key, _)| key == name) {
+            Some(index) => index,
+            None => {
+                self.children.push((name.to_string(), Node::default()));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index].1
+    }
+}
+
+/// Deserializer over a query tree node
+struct NodeDeserializer<'a>(&'a Node);
+
+impl<'a> NodeDeserializer<'a> {
+    fn first_value(&self) -> Result<ValueDeserializer<'a>, DeError> {
+        self.0
+            .values
+            .first()
+            .map(|value| ValueDeserializer(value))
+            .ok_or_else(|| de::Error::custom("expected a value, found nested parameters"))
+    }
+}
+
+macro_rules! forward_to_first_value {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+                self.first_value()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for NodeDeserializer<'a> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        if !self.0.children.is_empty() {
+            self.deserialize_map(visitor)
+        } else if self.0.values.len() > 1 {
+            self.deserialize_seq(visitor)
+        } else {
+            self.first_value()?.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        if self.0.children.is_empty() {
+            visitor.visit_seq(ValuesAccess(self.0.values.iter()))
+        } else {
+            visitor.visit_seq(ChildrenAccess(self.0.indexed_children().into_iter()))
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, DeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        if self.0.children.is_empty() && !self.0.values.is_empty() {
+            return Err(de::Error::custom("expected nested parameters, found a value"));
+        }
+        visitor.visit_map(EntriesAccess {
+            entries: self.0.children.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_first_value! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf deserialize_unit
+        deserialize_identifier
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.first_value()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.first_value()?.deserialize_enum(name, variants, visitor)
+    }
+}
+
+/// Deserializer over a single query value
+struct ValueDeserializer<'a>(&'a str);
+
+impl ValueDeserializer<'_> {
+    fn parse<T>(&self) -> Result<T, DeError>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.0.parse().map_err(de::Error::custom)
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_str(self.0)
+    }
+
+    /// Flag-style keys (`?debug`) and common spellings count as booleans
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self.0 {
+            "" | "true" | "1" | "on" | "yes" => visitor.visit_bool(true),
+            "false" | "0" | "off" | "no" => visitor.visit_bool(false),
+            other => Err(de::Error::custom(format!("invalid boolean '{}'", other))),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8, deserialize_i16 => visit_i16, deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64, deserialize_i128 => visit_i128, deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16, deserialize_u32 => visit_u32, deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128, deserialize_f32 => visit_f32, deserialize_f64 => visit_f64,
+        deserialize_char => visit_char
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// A single value for a sequence field becomes a one-element sequence
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_seq(SingleAccess(Some(self.0)))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        let de: StrDeserializer<'_, DeError> = self.0.into_deserializer();
+        de.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// Sequence access over repeated values of one key
+struct ValuesAccess<'a>(std::slice::Iter<'a, String>);
+
+impl<'de> SeqAccess<'de> for ValuesAccess<'_> {
+    type Error = DeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, DeError> {
+        self.0
+            .next()
+            .map(|value| seed.deserialize(ValueDeserializer(value)))
+            .transpose()
+    }
+}
+
+/// Sequence access over a single value
+struct SingleAccess<'a>(Option<&'a str>);
+
+impl<'de> SeqAccess<'de> for SingleAccess<'_> {
+    type Error = DeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, DeError> {
+        self.0
+            .take()
+            .map(|value| seed.deserialize(ValueDeserializer(value)))
+            .transpose()
+    }
+}
+
+/// Sequence access over nested keys (`a[0]=x&a[1]=y`)
+struct ChildrenAccess<'a>(std::vec::IntoIter<&'a Node>);
+
+impl<'de> SeqAccess<'de> for ChildrenAccess<'_> {
+    type Error = DeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, DeError> {
+        self.0
+            .next()
+            .map(|node| seed.deserialize(NodeDeserializer(node)))
+            .transpose()
+    }
+}
+
+/// Map access over nested keys
+struct EntriesAccess<'a> {
+    entries: std::slice::Iter<'a, (String, Node)>,
+    value: Option<&'a Node>,
+}
+
+impl<'de> MapAccess<'de> for EntriesAccess<'_> {
+    type Error = DeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, DeError> {
+        match self.entries.next() {
+            Some((key, node)) => {
+                self.value = Some(node);
+                let de: StrDeserializer<'_, DeError> = key.as_str().into_deserializer();
+                seed.deserialize(de).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+        let node = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before key"))?;
+        seed.deserialize(NodeDeserializer(node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_style_keys_match_the_plain_name() {
+        let query = QueryParams::parse("tag[]=a&tag[]=b");
+        assert_eq!(query.get("tag").map(String::as_str), Some("a"));
+        assert!(query.contains_key("tag"));
+        assert_eq!(query.get_all("tag"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn indexed_keys_deserialize_in_index_order() {
+        let query = QueryParams::parse("a[1]=second&a[0]=first&a[10]=last");
+        #[derive(serde::Deserialize)]
+        struct List {
+            a: Vec<String>,
+        }
+        let list: List = query.deserialize().unwrap();
+        assert_eq!(list.a, vec!["first", "second", "last"]);
+    }
+}
@@ -0,0 +1,524 @@
+//! Rate limit module for dia framework
+//!
+//! Provides the RateLimitMiddleware, limiting requests per client with a token
+//! bucket or a sliding window, and the store that keeps the counters.
+
+use crate::middleware::Middleware;
+use crate::path_pattern::PatternError;
+use crate::{PathPattern, Request, Response};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of checks between purges of idle clients in `MemoryRateLimitStore`
+const PURGE_INTERVAL: u64 = 1024;
+
+/// Function deriving the client key from a request
+pub type KeyFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// How requests are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Bucket of `requests` tokens refilled evenly over the period, allowing
+    /// short bursts
+    #[default]
+    TokenBucket,
+    /// At most `requests` in any period-long window
+    SlidingWindow,
+}
+
+/// Number of requests allowed per period
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::rate_limit::{Algorithm, RateLimit};
+///
+/// let limit = RateLimit::per_minute(60).algorithm(Algorithm::SlidingWindow);
+/// assert_eq!(limit.requests(), 60);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed per period
+    requests: u64,
+    /// Length of the period
+    period: Duration,
+    /// Counting algorithm
+    algorithm: Algorithm,
+}
+
+impl RateLimit {
+    /// Allow `requests` per `period` with a token bucket
+    ///
+    /// # Panics
+    ///
+    /// Panics if `requests` or `period` is zero.
+    pub fn new(requests: u64, period: Duration) -> Self {
+        assert!(requests > 0 && !period.is_zero(), "Rate limit needs a request count and a period");
+        Self {
+            requests,
+            period,
+            algorithm: Algorithm::TokenBucket,
+        }
+    }
+
+    /// Allow `requests` per second
+    pub fn per_second(requests: u64) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+
+    /// Allow `requests` per minute
+    pub fn per_minute(requests: u64) -> Self {
+        Self::new(requests, Duration::from_secs(60))
+    }
+
+    /// Allow `requests` per hour
+    pub fn per_hour(requests: u64) -> Self {
+        Self::new(requests, Duration::from_secs(60 * 60))
+    }
+
+    /// Set the counting algorithm
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Get the number of requests allowed per period
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Get the period
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Get the counting algorithm
+    pub fn get_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+}
+
+/// Outcome of counting a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// Whether the request may proceed
+    pub allowed: bool,
+    /// Requests still allowed right now
+    pub remaining: u64,
+    /// Time until the client's quota is fully restored
+    pub reset: Duration,
+    /// Time until the next request would be allowed, when refused
+    pub retry_after: Duration,
+}
+
+/// Storage for rate limit counters
+///
+/// Implement it on a shared store such as Redis when running several
+/// instances; `MemoryRateLimitStore` is the default.
+pub trait RateLimitStore: Send + Sync {
+    /// Count a request for `key` against `limit`
+    ///
+    /// Must be atomic per key, and only consume quota for allowed requests.
+    fn check(&self, key: &str, limit: &RateLimit) -> Pin<Box<dyn Future<Output = Decision> + Send + '_>>;
+}
+
+/// Counters of one client
+#[derive(Debug)]
+enum Counter {
+    /// Tokens left and when they were last refilled
+    Bucket { tokens: f64, updated: Instant },
+    /// Times of the requests in the current window
+    Window(VecDeque<Instant>),
+}
+
+impl Counter {
+    /// Count a request, consuming quota if it is allowed
+    fn check(&mut self, limit: &RateLimit, now: Instant) -> Decision {
+        let max = limit.requests as f64;
+        let period = limit.period.as_secs_f64();
+        match self {
+            Counter::Bucket { tokens, updated } => {
+                let rate = max / period;
+                *tokens = (*tokens + now.duration_since(*updated).as_secs_f64() * rate).min(max);
+                *updated = now;
+
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                Decision {
+                    allowed,
+                    remaining: *tokens as u64,
+                    reset: Duration::from_secs_f64((max - *tokens) / rate),
+                    retry_after: Duration::from_secs_f64((1.0 - *tokens).max(0.0) / rate),
+                }
+            }
+            Counter::Window(requests) => {
+                while requests.front().is_some_and(|at| now.duration_since(*at) >= limit.period) {
+                    requests.pop_front();
+                }
+
+                let allowed = (requests.len() as u64) < limit.requests;
+                if allowed {
+                    requests.push_back(now);
+                }
+                let expiry = |at: Option<&Instant>| at.map_or(Duration::ZERO, |at| limit.period - now.duration_since(*at));
+                Decision {
+                    allowed,
+                    remaining: limit.requests - requests.len() as u64,
+                    reset: expiry(requests.back()),
+                    retry_after: if allowed { Duration::ZERO } else { expiry(requests.front()) },
+                }
+            }
+        }
+    }
+}
+
+/// In-process rate limit store, forgetting clients once their quota is restored
+#[derive(Debug, Default)]
+pub struct MemoryRateLimitStore {
+    /// Counters and the time their quota is fully restored, by key
+    counters: Mutex<HashMap<String, (Counter, Instant)>>,
+    /// Checks since the last purge
+    checks: AtomicU64,
+}
+
+impl MemoryRateLimitStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for MemoryRateLimitStore {
+    fn check(&self, key: &str, limit: &RateLimit) -> Pin<Box<dyn Future<Output = Decision> + Send + '_>> {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+
+        if self.checks.fetch_add(1, Ordering::Relaxed) % PURGE_INTERVAL == PURGE_INTERVAL - 1 {
+            counters.retain(|_, (_, restored)| *restored > now);
+        }
+
+        let (counter, restored) = counters.entry(key.to_string()).or_insert_with(|| {
+            let counter = match limit.algorithm {
+                Algorithm::TokenBucket => Counter::Bucket {
+                    tokens: limit.requests as f64,
+                    updated: now,
+                },
+                Algorithm::SlidingWindow => Counter::Window(VecDeque::new()),
+            };
+            (counter, now)
+        });
+        let decision = counter.check(limit, now);
+        *restored = now + decision.reset;
+        Box::pin(async move { decision })
+    }
+}
+
+/// How clients are told apart
+enum ClientKey {
+    /// Client IP: the socket peer, or `X-Forwarded-For` behind a trusted proxy
+    RemoteIp,
+    /// `Request::principal`, falling back to the client IP
+    Principal,
+    /// A custom function
+    Custom(KeyFn),
+}
+
+/// Rate limit applied to a request, for the response headers
+struct Applied(RateLimit, Decision);
+
+/// Middleware limiting the request rate of each client
+///
+/// Allowed responses carry `RateLimit-Limit`, `RateLimit-Remaining`,
+/// `RateLimit-Reset` and `RateLimit-Policy` headers; refused requests get a
+/// 429 with `Retry-After` as well. Routes can have their own limit, counted
+/// separately from the default one.
+///
+/// Clients are told apart by the IP of the socket peer. Behind a reverse
+/// proxy, list it with `trusted_proxies` so the client IP is read from
+/// `X-Forwarded-For` instead; the header is ignored on connections from any
+/// other address, since clients can set it to anything.
+///
+/// Requests without a client key (no peer address, or `None` from a custom
+/// key function) are not limited. To key by principal, add the middleware
+/// after the authentication middleware.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::Application;
+/// use dia_core::rate_limit::{Algorithm, RateLimit, RateLimitMiddleware};
+///
+/// let app = Application::new().middleware(
+///     RateLimitMiddleware::new(RateLimit::per_minute(120))
+///         .route("POST /login", RateLimit::per_minute(5).algorithm(Algorithm::SlidingWindow))?,
+/// );
+/// # Ok::<(), dia_core::path_pattern::PatternError>(())
+/// ```
+pub struct RateLimitMiddleware {
+    /// Limit for routes without an override
+    limit: RateLimit,
+    /// Per-route limits, the first match wins
+    routes: Vec<(PathPattern, RateLimit)>,
+    /// How clients are told apart
+    key: ClientKey,
+    /// Proxies whose `X-Forwarded-For` header is believed
+    trusted_proxies: Vec<IpAddr>,
+    /// Counter storage
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimitMiddleware {
+    /// Limit each client IP to `limit`
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            routes: Vec::new(),
+            key: ClientKey::RemoteIp,
+            trusted_proxies: Vec::new(),
+            store: Arc::new(MemoryRateLimitStore::new()),
+        }
+    }
+
+    /// Count requests per client IP (the default)
+    pub fn key_by_ip(mut self) -> Self {
+        self.key = ClientKey::RemoteIp;
+        self
+    }
+
+    /// Trust `X-Forwarded-For` on connections from these proxies
+    ///
+    /// The client IP is the rightmost address in the header that is not
+    /// itself a trusted proxy.
+    pub fn trusted_proxies<I: IntoIterator<Item = IpAddr>>(mut self, proxies: I) -> Self {
+        self.trusted_proxies = proxies.into_iter().collect();
+        self
+    }
+
+    /// Count requests per authenticated principal, or per client IP for
+    /// anonymous requests
+    pub fn key_by_principal(mut self) -> Self {
+        self.key = ClientKey::Principal;
+        self
+    }
+
+    /// Count requests per key returned by a function
+    pub fn key_fn<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = ClientKey::Custom(Arc::new(key));
+        self
+    }
+
+    /// Use a different limit for requests matching a pattern
+    ///
+    /// Patterns are written like `AuthMiddleware` public paths, such as
+    /// `POST /login` or `/search/**`. Fails if `pattern` is not a valid
+    /// pattern.
+    pub fn route<P>(mut self, pattern: P, limit: RateLimit) -> Result<Self, PatternError>
+    where
+        P: TryInto<PathPattern>,
+        P::Error: Into<PatternError>,
+    {
+        self.routes.push((pattern.try_into().map_err(Into::into)?, limit));
+        Ok(self)
+    }
+
+    /// Set the store holding the counters
+    pub fn store<S: RateLimitStore + 'static>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Get the key of the client making a request
+    fn client_key(&self, req: &Request) -> Option<String> {
+        let ip = || self.client_ip(req).map(|ip| format!("ip:{}", ip));
+        match &self.key {
+            ClientKey::RemoteIp => ip(),
+            ClientKey::Principal => match req.principal() {
+                Some(principal) => Some(format!("principal:{}:{}", principal.scheme(), principal.name())),
+                None => ip(),
+            },
+            ClientKey::Custom(key) => key(req),
+        }
+    }
+
+    /// Get the client IP, following `X-Forwarded-For` through trusted proxies
+    fn client_ip(&self, req: &Request) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        if !self.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+        let forwarded: Vec<&str> = req.headers().get_all("x-forwarded-for");
+        let hops = forwarded.iter().flat_map(|value| value.split(',')).map(str::trim);
+        let mut client = peer;
+        for hop in hops.rev() {
+            match hop.parse::<IpAddr>() {
+                Ok(ip) if self.trusted_proxies.contains(&ip) => client = ip,
+                Ok(ip) => return Some(ip),
+                // An unparsable hop was added by an untrusted party
+                Err(_) => break,
+            }
+        }
+        Some(client)
+    }
+
+    /// Find the limit for a request and the name of its counter
+    fn limit_for(&self, req: &Request) -> (&'static str, usize, &RateLimit) {
+        match self
+            .routes
+            .iter()
+            .position(|(pattern, _)| pattern.matches(req.method(), req.path()))
+        {
+            Some(index) => ("route", index, &self.routes[index].1),
+            None => ("default", 0, &self.limit),
+        }
+    }
+}
+
+impl Middleware for RateLimitMiddleware {
+    fn before_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+    ) -> Pin<Box<dyn Future<Output = Option<Response>> + Send + 'a>> {
+        Box::pin(async move {
+            let client = self.client_key(req)?;
+            let (scope, index, limit) = self.limit_for(req);
+            let decision = self.store.check(&format!("{}:{}:{}", scope, index, client), limit).await;
+            req.extensions_mut().insert(Applied(*limit, decision));
+            if decision.allowed {
+                return None;
+            }
+
+            log::debug!("Rate limited {} {} for {}", req.method(), req.path(), client);
+            let retry_after = ceil_secs(decision.retry_after).max(1);
+            Some(headers(Response::too_many_requests("Too many requests"), limit, &decision)
+                .header("Retry-After", retry_after.to_string()))
+        })
+    }
+
+    fn after_request<'a>(
+        &'a self,
+        req: &'a Request,
+        resp: Response,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'a>> {
+        let resp = match req.extensions().get::<Applied>() {
+            Some(Applied(limit, decision)) if decision.allowed => headers(resp, limit, decision),
+            _ => resp,
+        };
+        Box::pin(async move { resp })
+    }
+
+    fn name(&self) -> &str {
+        "RateLimit"
+    }
+}
+
+/// Add the `RateLimit-*` headers
+fn headers(resp: Response, limit: &RateLimit, decision: &Decision) -> Response {
+    resp.header("RateLimit-Limit", limit.requests.to_string())
+        .header("RateLimit-Remaining", decision.remaining.to_string())
+        .header("RateLimit-Reset", ceil_secs(decision.reset).to_string())
+        .header(
+            "RateLimit-Policy",
+            format!("{};w={}", limit.requests, ceil_secs(limit.period)),
+        )
+}
+
+/// Round a duration up to whole seconds
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::HandlerFn;
+    use crate::middleware::MiddlewareChain;
+    use actix_web::test::TestRequest;
+
+    /// Send a GET from `peer` with an optional `X-Forwarded-For` header
+    fn call(chain: &MiddlewareChain, peer: &str, forwarded_for: Option<&str>) -> Response {
+        let mut req = TestRequest::get().uri("/items").peer_addr(peer.parse().unwrap());
+        if let Some(forwarded_for) = forwarded_for {
+            req = req.insert_header(("x-forwarded-for", forwarded_for));
+        }
+        let handler: HandlerFn = Arc::new(|_req, _resp| Box::pin(async { Response::ok_text("ok") }));
+        futures::executor::block_on(chain.handle(Request::new(req.to_http_request()), handler))
+    }
+
+    #[test]
+    fn token_bucket_refills_over_the_period() {
+        let limit = RateLimit::per_second(2);
+        let start = Instant::now();
+        let mut counter = Counter::Bucket { tokens: 2.0, updated: start };
+        assert!(counter.check(&limit, start).allowed);
+        assert!(counter.check(&limit, start).allowed);
+
+        let refused = counter.check(&limit, start);
+        assert!(!refused.allowed);
+        assert_eq!(refused.retry_after, Duration::from_millis(500));
+        assert!(counter.check(&limit, start + Duration::from_millis(500)).allowed);
+    }
+
+    #[test]
+    fn sliding_window_forgets_old_requests() {
+        let limit = RateLimit::per_second(2).algorithm(Algorithm::SlidingWindow);
+        let start = Instant::now();
+        let mut counter = Counter::Window(VecDeque::new());
+        assert!(counter.check(&limit, start).allowed);
+        assert!(counter.check(&limit, start + Duration::from_millis(400)).allowed);
+
+        let refused = counter.check(&limit, start + Duration::from_millis(900));
+        assert!(!refused.allowed);
+        assert_eq!(refused.retry_after, Duration::from_millis(100));
+        assert!(counter.check(&limit, start + Duration::from_secs(1)).allowed);
+        assert!(!counter.check(&limit, start + Duration::from_secs(1)).allowed);
+    }
+
+    #[test]
+    fn responses_carry_rate_limit_headers() {
+        let chain = MiddlewareChain::new(vec![Box::new(RateLimitMiddleware::new(RateLimit::per_minute(2)))]);
+
+        let allowed = call(&chain, "203.0.113.7:4000", None);
+        assert_eq!(allowed.get_status(), 200);
+        assert_eq!(allowed.get_header("ratelimit-limit"), Some("2"));
+        assert_eq!(allowed.get_header("ratelimit-remaining"), Some("1"));
+        assert_eq!(allowed.get_header("ratelimit-policy"), Some("2;w=60"));
+        assert!(allowed.get_header("retry-after").is_none());
+
+        assert_eq!(call(&chain, "203.0.113.7:4001", None).get_header("ratelimit-remaining"), Some("0"));
+        let refused = call(&chain, "203.0.113.7:4002", None);
+        assert_eq!(refused.get_status(), 429);
+        assert_eq!(refused.get_header("ratelimit-remaining"), Some("0"));
+        assert_eq!(refused.get_header("retry-after"), Some("30"));
+        assert_eq!(refused.get_header("ratelimit-reset"), Some("60"));
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        let chain = MiddlewareChain::new(vec![Box::new(RateLimitMiddleware::new(RateLimit::per_minute(1)))]);
+        assert_eq!(call(&chain, "203.0.113.7:4000", Some("198.51.100.1")).get_status(), 200);
+        // A new spoofed address does not reset the bucket
+        assert_eq!(call(&chain, "203.0.113.7:4000", Some("198.51.100.2")).get_status(), 429);
+    }
+
+    #[test]
+    fn forwarded_for_identifies_clients_behind_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let limit = RateLimitMiddleware::new(RateLimit::per_minute(1)).trusted_proxies([proxy]);
+        let chain = MiddlewareChain::new(vec![Box::new(limit)]);
+
+        assert_eq!(call(&chain, "10.0.0.1:4000", Some("198.51.100.1")).get_status(), 200);
+        assert_eq!(call(&chain, "10.0.0.1:4000", Some("198.51.100.2, 10.0.0.1")).get_status(), 200);
+        // Addresses left of the first untrusted hop are the client's to forge
+        assert_eq!(call(&chain, "10.0.0.1:4000", Some("192.0.2.9, 198.51.100.1")).get_status(), 429);
+    }
+}
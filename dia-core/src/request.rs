@@ -2,52 +2,267 @@
 //! 
 //! Provides the Request struct for handling HTTP requests.
 
-use serde_json::Value;
+use crate::cookie::{self, Cookie, CookieJar, CookieKey};
+use crate::extensions::Extensions;
+use crate::jwt::Claims;
+use crate::application::AppState;
+use crate::middleware::Principal;
+use crate::header::HeaderMap;
+use crate::multipart::Multipart;
+use crate::query::QueryParams;
+use actix_web::mime;
+use actix_web::web::Bytes;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Default maximum request body size (1 MiB)
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Default maximum size of a single multipart part (10 MiB)
+pub const DEFAULT_MAX_PART_SIZE: usize = 10 * 1024 * 1024;
+
+/// Default maximum total size of a multipart body (50 MiB)
+pub const DEFAULT_MAX_MULTIPART_SIZE: usize = 50 * 1024 * 1024;
+
+/// Default size above which multipart parts are spilled to disk (256 KiB)
+pub const DEFAULT_PART_MEMORY_LIMIT: usize = 256 * 1024;
+
+/// Request body settings shared with the route adapter
+#[derive(Debug, Clone)]
+pub struct BodyConfig {
+    /// Maximum accepted body size in bytes (multipart bodies excepted)
+    pub max_size: usize,
+    /// Maximum size of a single multipart part in bytes
+    pub max_part_size: usize,
+    /// Maximum total size of a multipart body in bytes
+    pub max_multipart_size: usize,
+    /// Multipart parts larger than this are written to a temporary file
+    pub part_memory_limit: usize,
+}
+
+impl Default for BodyConfig {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_MAX_BODY_SIZE,
+            max_part_size: DEFAULT_MAX_PART_SIZE,
+            max_multipart_size: DEFAULT_MAX_MULTIPART_SIZE,
+            part_memory_limit: DEFAULT_PART_MEMORY_LIMIT,
+        }
+    }
+}
+
+/// Errors raised while reading or decoding a request body
+#[derive(Debug, Error)]
+pub enum BodyError {
+    /// The body exceeds the configured maximum size
+    #[error("Request body exceeds the limit of {limit} bytes")]
+    TooLarge {
+        /// Configured limit in bytes
+        limit: usize,
+    },
+    /// The body could not be read from the connection
+    #[error("Failed to read request body: {0}")]
+    Payload(String),
+    /// The body is not valid UTF-8
+    #[error("Request body is not valid UTF-8")]
+    InvalidUtf8,
+    /// The body is not valid JSON
+    #[error("Invalid JSON body: {0}")]
+    InvalidJson(String),
+    /// The body uses a content coding the server cannot decode
+    #[error("Unsupported content encoding {0}")]
+    UnsupportedEncoding(String),
+    /// The compressed body could not be decoded
+    #[error("Invalid compressed body: {0}")]
+    InvalidEncoding(String),
+    /// The request has the wrong content type for the requested body
+    #[error("Unsupported content type {}, expected {expected}", found.as_deref().unwrap_or("(none)"))]
+    UnsupportedMediaType {
+        /// The content type the handler expects
+        expected: &'static str,
+        /// The content type sent by the client
+        found: Option<String>,
+    },
+}
+
+impl BodyError {
+    /// HTTP status code matching this error
+    pub fn status(&self) -> u16 {
+        match self {
+            BodyError::TooLarge { .. } => 413,
+            BodyError::UnsupportedMediaType { .. } | BodyError::UnsupportedEncoding(_) => 415,
+            _ => 400,
+        }
+    }
+}
+
+impl From<BodyError> for crate::Response {
+    fn from(err: BodyError) -> Self {
+        crate::Response::new().status(err.status()).text(err.to_string())
+    }
+}
+
+/// Errors raised by typed path parameter access
+#[derive(Debug, Error)]
+pub enum ParamError {
+    /// The route has no parameter with this name
+    #[error("Missing path parameter '{0}'")]
+    Missing(String),
+    /// The parameter value could not be parsed into the requested type
+    #[error("Invalid path parameter '{name}' = '{value}': {reason}")]
+    Invalid {
+        /// Parameter name
+        name: String,
+        /// Raw parameter value
+        value: String,
+        /// Parse error message
+        reason: String,
+    },
+}
+
+impl From<ParamError> for crate::Response {
+    fn from(err: ParamError) -> Self {
+        crate::Response::bad_request(err.to_string())
+    }
+}
+
+/// A single field that failed typed extraction
+#[derive(Debug, Clone)]
+pub struct FieldError {
+    /// Path of the offending field (e.g. `user.email`), `None` for the whole value
+    pub field: Option<String>,
+    /// Deserialization error message
+    pub message: String,
+}
+
+/// Unified error for typed query, path and body extraction
+///
+/// Converts into a JSON response naming the offending field, with status 400
+/// unless the underlying body error calls for 413 or 415. Deserialization
+/// stops at the first error, so only the first offending field is reported.
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    /// The query string does not match the target type
+    #[error("Invalid query string")]
+    Query(Vec<FieldError>),
+    /// The path parameters do not match the target type
+    #[error("Invalid path parameters")]
+    Path(Vec<FieldError>),
+    /// The request body does not match the target type
+    #[error("Invalid request body")]
+    Body(Vec<FieldError>),
+    /// The request body could not be read or has the wrong content type
+    #[error(transparent)]
+    Payload(#[from] BodyError),
+    /// A single path parameter is missing or invalid
+    #[error(transparent)]
+    Param(#[from] ParamError),
+}
+
+impl ExtractError {
+    /// HTTP status code matching this error
+    pub fn status(&self) -> u16 {
+        match self {
+            ExtractError::Payload(err) => err.status(),
+            _ => 400,
+        }
+    }
+
+    /// The field that failed to deserialize
+    ///
+    /// Holds the first offending field only: deserialization stops there.
+    pub fn fields(&self) -> &[FieldError] {
+        match self {
+            ExtractError::Query(fields) | ExtractError::Path(fields) | ExtractError::Body(fields) => fields,
+            _ => &[],
+        }
+    }
+
+    /// Build the field error for the first serde error, with its field path
+    fn field<E: Display>(err: serde_path_to_error::Error<E>) -> Vec<FieldError> {
+        let path = err.path().to_string();
+        let field = if path == "." { None } else { Some(path) };
+        vec![FieldError {
+            field,
+            message: err.into_inner().to_string(),
+        }]
+    }
+}
+
+impl From<ExtractError> for crate::Response {
+    fn from(err: ExtractError) -> Self {
+        let fields: Vec<Value> = err
+            .fields()
+            .iter()
+            .map(|f| json!({"field": f.field, "message": f.message}))
+            .collect();
+        crate::Response::new()
+            .status(err.status())
+            .json(json!({"error": err.to_string(), "fields": fields}))
+    }
+}
 
 /// HTTP request wrapper that provides a simplified interface
 #[derive(Debug, Clone)]
 pub struct Request {
     /// HTTP method
     method: String,
+    /// HTTP protocol version, such as `HTTP/1.1`
+    version: String,
     /// Request path
     path: String,
     /// Request headers
-    headers: HashMap<String, String>,
-    /// Request body as JSON value
-    body: Option<Value>,
+    headers: HeaderMap,
+    /// Raw request body
+    body: Bytes,
+    /// Request body parsed as JSON
+    json: Option<Value>,
+    /// Request body parsed as multipart/form-data
+    multipart: Option<Multipart>,
     /// Path parameters
     path_params: HashMap<String, String>,
     /// Query parameters  
-    query_params: HashMap<String, String>,
+    query_params: QueryParams,
+    /// Raw query string
+    query_string: String,
     /// Remote IP address
     remote_ip: Option<String>,
+    /// Address of the socket peer
+    peer_addr: Option<SocketAddr>,
+    /// Cookies sent by the client
+    cookies: CookieJar,
+    /// Key for signed and encrypted cookies
+    cookie_key: Option<CookieKey>,
+    /// Values attached by middlewares
+    extensions: Extensions,
+    /// Values shared by the application
+    app_data: Arc<Extensions>,
 }
 
 impl Request {
     /// Create a new Request from actix-web HttpRequest
     pub fn new(req: actix_web::HttpRequest) -> Self {
-        let query_params = req
-            .query_string()
-            .split('&')
-            .filter_map(|param| {
-                let mut parts = param.splitn(2, '=');
-                match (parts.next(), parts.next()) {
-                    (Some(key), Some(value)) => Some((
-                        urlencoding::decode(key).unwrap_or_default().to_string(),
-                        urlencoding::decode(value).unwrap_or_default().to_string(),
-                    )),
-                    _ => None,
-                }
-            })
-            .collect();
+        let query_params = QueryParams::parse(req.query_string());
+
+        let headers = HeaderMap::from(req.headers());
 
-        let headers = req
-            .headers()
+        let mut cookies = CookieJar::new();
+        for value in headers.get_all("cookie") {
+            for cookie in cookie::parse_header(value) {
+                cookies.add_original(cookie);
+            }
+        }
+
+        let path_params = req
+            .match_info()
             .iter()
-            .filter_map(|(name, value)| {
-                value.to_str().ok().map(|v| (name.to_string(), v.to_string()))
-            })
+            .map(|(name, value)| (name.to_string(), value.to_string()))
             .collect();
 
         let remote_ip = req
@@ -57,12 +272,21 @@ impl Request {
 
         Self {
             method: req.method().to_string(),
+            version: format!("{:?}", req.version()),
             path: req.path().to_string(),
             headers,
-            body: None,
-            path_params: HashMap::new(),
+            body: Bytes::new(),
+            json: None,
+            multipart: None,
+            path_params,
             query_params,
+            query_string: req.query_string().to_string(),
             remote_ip,
+            peer_addr: req.peer_addr(),
+            cookies,
+            cookie_key: None,
+            extensions: Extensions::new(),
+            app_data: Arc::new(Extensions::new()),
         }
     }
 
@@ -71,70 +295,342 @@ impl Request {
         &self.method
     }
 
+    /// Get the HTTP protocol version, such as `HTTP/1.1`
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
     /// Get the request path
     pub fn path(&self) -> &str {
         &self.path
     }
 
-    /// Get a header value by name
-    pub fn header(&self, name: &str) -> Option<&String> {
+    /// Get a header value by name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
         self.headers.get(name)
     }
 
-    /// Get all headers as a HashMap
-    pub fn headers(&self) -> &HashMap<String, String> {
+    /// Get a header value by name as raw bytes
+    pub fn header_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.headers.get_bytes(name)
+    }
+
+    /// Get every value of a repeated header
+    pub fn header_all(&self, name: &str) -> Vec<&str> {
+        self.headers.get_all(name)
+    }
+
+    /// Get all headers
+    pub fn headers(&self) -> &HeaderMap {
         &self.headers
     }
 
     /// Get a query parameter by name
+    ///
+    /// Returns the first value when the key is repeated, and an empty string
+    /// for flag-style keys such as `?debug`.
     pub fn query(&self, name: &str) -> Option<&String> {
         self.query_params.get(name)
     }
 
+    /// Get every value of a repeated query parameter, in order
+    pub fn query_all(&self, name: &str) -> Vec<&String> {
+        self.query_params.get_all(name)
+    }
+
+    /// Check if a query parameter is present, with or without a value
+    pub fn has_query(&self, name: &str) -> bool {
+        self.query_params.contains_key(name)
+    }
+
+    /// Get the query parameters nested under a bracketed key (`filter[status]`)
+    pub fn query_nested(&self, name: &str) -> QueryParams {
+        self.query_params.nested(name)
+    }
+
     /// Get all query parameters
-    pub fn query_params(&self) -> &HashMap<String, String> {
+    pub fn query_params(&self) -> &QueryParams {
         &self.query_params
     }
 
+    /// Get the raw query string
+    pub fn query_string(&self) -> &str {
+        &self.query_string
+    }
+
+    /// Deserialize the query string into `T`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use dia_core::Request;
+    /// use actix_web::test::TestRequest;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct Page {
+    ///     page: u32,
+    ///     per_page: Option<u32>,
+    /// }
+    ///
+    /// let req = Request::new(TestRequest::get().uri("/users?page=2").to_http_request());
+    /// let page: Page = req.query_as().unwrap();
+    /// assert_eq!(page.page, 2);
+    /// assert!(page.per_page.is_none());
+    ///
+    /// let req = Request::new(TestRequest::get().uri("/users?page=two").to_http_request());
+    /// let err = req.query_as::<Page>().unwrap_err();
+    /// assert_eq!(err.fields()[0].field.as_deref(), Some("page"));
+    /// ```
+    pub fn query_as<T: DeserializeOwned>(&self) -> Result<T, ExtractError> {
+        self.query_params
+            .deserialize()
+            .map_err(|e| ExtractError::Query(ExtractError::field(e)))
+    }
+
     /// Get a path parameter by name
     pub fn param(&self, name: &str) -> Option<&String> {
         self.path_params.get(name)
     }
 
+    /// Get a path parameter parsed into `T`
+    ///
+    /// Fails with a `ParamError` (which converts into a 400 response) when the
+    /// parameter is missing or does not parse.
+    pub fn param_as<T>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self
+            .param(name)
+            .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+        value.parse().map_err(|e: T::Err| ParamError::Invalid {
+            name: name.to_string(),
+            value: value.clone(),
+            reason: e.to_string(),
+        })
+    }
+
+    /// Get all path parameters
+    pub fn path_params(&self) -> &HashMap<String, String> {
+        &self.path_params
+    }
+
+    /// Deserialize the path parameters into `T`
+    pub fn path_as<T: DeserializeOwned>(&self) -> Result<T, ExtractError> {
+        QueryParams::from_pairs(self.path_params.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+            .deserialize()
+            .map_err(|e| ExtractError::Path(ExtractError::field(e)))
+    }
+
     /// Set path parameters (used internally by routing)
     pub fn set_path_params(&mut self, params: HashMap<String, String>) {
         self.path_params = params;
     }
 
     /// Get the request body as JSON
+    ///
+    /// Populated for requests with a JSON content type and a non-empty body.
     pub fn json(&self) -> Option<&Value> {
-        self.body.as_ref()
+        self.json.as_ref()
+    }
+
+    /// Get the JSON body, failing if the request did not send JSON
+    pub fn require_json(&self) -> Result<&Value, BodyError> {
+        if !self.is_json() {
+            return Err(BodyError::UnsupportedMediaType {
+                expected: "application/json",
+                found: self.content_type().map(str::to_string),
+            });
+        }
+        self.json
+            .as_ref()
+            .ok_or_else(|| BodyError::InvalidJson("empty body".to_string()))
+    }
+
+    /// Deserialize the JSON or form-urlencoded request body into `T`
+    pub fn body_as<T: DeserializeOwned>(&self) -> Result<T, ExtractError> {
+        if self.is_form() {
+            return self
+                .form()?
+                .deserialize()
+                .map_err(|e| ExtractError::Body(ExtractError::field(e)));
+        }
+        self.require_json()?;
+        let mut de = serde_json::Deserializer::from_slice(&self.body);
+        serde_path_to_error::deserialize(&mut de).map_err(|e| ExtractError::Body(ExtractError::field(e)))
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` body
+    ///
+    /// The fields support the same repeated and bracketed keys as the query
+    /// string.
+    pub fn form(&self) -> Result<QueryParams, BodyError> {
+        if !self.is_form() {
+            return Err(BodyError::UnsupportedMediaType {
+                expected: "application/x-www-form-urlencoded",
+                found: self.content_type().map(str::to_string),
+            });
+        }
+        Ok(QueryParams::parse(self.text()?))
+    }
+
+    /// Get the parsed `multipart/form-data` body
+    ///
+    /// Multipart bodies are parsed by the route adapter within the limits
+    /// configured on `Application`; `body()` is empty for these requests.
+    pub fn multipart(&self) -> Result<&Multipart, BodyError> {
+        self.multipart
+            .as_ref()
+            .ok_or_else(|| BodyError::UnsupportedMediaType {
+                expected: "multipart/form-data",
+                found: self.content_type().map(str::to_string),
+            })
+    }
+
+    /// Set the parsed multipart body (used internally)
+    pub fn set_multipart(&mut self, multipart: Multipart) {
+        self.multipart = Some(multipart);
     }
 
     /// Set the request body (used internally)
     pub fn set_body(&mut self, body: Value) {
-        self.body = Some(body);
+        self.body = Bytes::from(body.to_string());
+        self.json = Some(body);
+    }
+
+    /// Get the raw request body
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Get the request body as UTF-8 text
+    pub fn text(&self) -> Result<&str, BodyError> {
+        std::str::from_utf8(&self.body).map_err(|_| BodyError::InvalidUtf8)
+    }
+
+    /// Set the raw request body, parsing it as JSON for JSON requests
+    pub fn set_body_bytes(&mut self, body: Bytes) -> Result<(), BodyError> {
+        self.json = if self.is_json() && !body.is_empty() {
+            let value = serde_json::from_slice(&body)
+                .map_err(|e| BodyError::InvalidJson(e.to_string()))?;
+            Some(value)
+        } else {
+            None
+        };
+        self.body = body;
+        Ok(())
     }
 
     /// Get the content type
     pub fn content_type(&self) -> Option<&str> {
-        self.header("content-type").map(|s| s.as_str())
+        self.header("content-type")
     }
 
     /// Check if the request is JSON
     pub fn is_json(&self) -> bool {
-        self.header("content-type")
-            .map(|ct| ct.contains("application/json"))
-            .unwrap_or(false)
+        self.media_type()
+            .is_some_and(|mime| mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON))
+    }
+
+    /// Parse the content type, matching type and subtype case-insensitively
+    fn media_type(&self) -> Option<mime::Mime> {
+        self.content_type()?.parse().ok()
+    }
+
+    /// Get a cookie sent by the client
+    pub fn cookie(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.cookies.get(name)
+    }
+
+    /// Get all cookies sent by the client
+    pub fn cookies(&self) -> impl Iterator<Item = &Cookie<'static>> {
+        self.cookies.iter()
+    }
+
+    /// Get a signed cookie, verifying it with the application cookie key
+    ///
+    /// Returns `None` if the cookie is missing, has been tampered with, or no
+    /// cookie secret is configured.
+    pub fn signed_cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        let key = self.cookie_key.as_ref()?;
+        self.cookies.signed(key.key()).get(name)
+    }
+
+    /// Get an encrypted cookie, decrypting it with the application cookie key
+    pub fn private_cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        let key = self.cookie_key.as_ref()?;
+        self.cookies.private(key.key()).get(name)
+    }
+
+    /// Set the cookie key (used internally)
+    pub fn set_cookie_key(&mut self, key: CookieKey) {
+        self.cookie_key = Some(key);
+    }
+
+    /// Check if the request is an HTML form submission
+    pub fn is_form(&self) -> bool {
+        self.media_type()
+            .is_some_and(|mime| mime.essence_str() == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str())
+    }
+
+    /// Check if the request is multipart/form-data
+    pub fn is_multipart(&self) -> bool {
+        self.media_type()
+            .is_some_and(|mime| mime.type_() == mime::MULTIPART && mime.subtype() == mime::FORM_DATA)
     }
 
     /// Get the remote IP address
+    ///
+    /// Taken from the `Forwarded` / `X-Forwarded-For` headers when present,
+    /// which any client can set; use `peer_addr` for security decisions.
     pub fn remote_ip(&self) -> Option<&String> {
         self.remote_ip.as_ref()
     }
 
+    /// Get the address of the connected peer, such as a reverse proxy
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
     /// Get the user agent
-    pub fn user_agent(&self) -> Option<&String> {
+    pub fn user_agent(&self) -> Option<&str> {
         self.header("user-agent")
     }
+
+    /// Get the verified bearer token claims, set by AuthMiddleware
+    pub fn claims(&self) -> Option<&Claims> {
+        self.extensions.get::<Claims>()
+    }
+
+    /// Get the authenticated caller, set by the authentication middlewares
+    pub fn principal(&self) -> Option<&Principal> {
+        self.extensions.get::<Principal>()
+    }
+
+    /// Get a value shared with `Application::data`
+    pub fn data<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.app_data.get::<T>()
+    }
+
+    /// Get a value set with `Application::state`
+    pub fn state(&self, key: &str) -> Option<&str> {
+        self.data::<AppState>()?.0.get(key).map(String::as_str)
+    }
+
+    /// Set the application data (used internally)
+    pub fn set_app_data(&mut self, data: Arc<Extensions>) {
+        self.app_data = data;
+    }
+
+    /// Get the values attached by middlewares
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Get the values attached by middlewares for modification
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
 }
\ No newline at end of file
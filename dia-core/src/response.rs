@@ -2,23 +2,51 @@
 //! 
 //! Provides the Response struct for building HTTP responses.
 
-use actix_web::{HttpResponse, http::StatusCode};
+use actix_web::{HttpResponse, body::SizedStream, http::StatusCode, mime, web::Bytes};
+use futures::{Stream, StreamExt};
 use serde::{Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use crate::cookie::{Cookie, CookieJar, CookieKey};
+use crate::files::{self, ByteRange, FileBody};
+use crate::header::HeaderMap;
+use crate::request::Request;
+
+/// Boxed error type produced by streaming bodies
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Boxed stream of body chunks
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, BoxError>> + Send>>;
 
 /// HTTP response builder that provides a simplified interface
 pub struct Response {
     /// HTTP status code
     status: StatusCode,
     /// Response headers
-    headers: HashMap<String, String>,
+    headers: HeaderMap,
     /// Response body
     body: ResponseBody,
+    /// Serialized JSON body, filled the first time it is measured or sent
+    json_bytes: OnceLock<Bytes>,
+    /// Cookies waiting to be signed or encrypted with the application key
+    sealed_cookies: Vec<(Cookie<'static>, CookieSeal)>,
+}
+
+/// How a pending cookie should be protected
+#[derive(Debug, Clone, Copy)]
+enum CookieSeal {
+    /// Signed: readable by the client but tamper-proof
+    Signed,
+    /// Encrypted: neither readable nor modifiable by the client
+    Private,
 }
 
 /// Enum representing different types of response bodies
-#[derive(Debug, Clone)]
 pub enum ResponseBody {
     /// Plain text response
     Text(String),
@@ -28,6 +56,88 @@ pub enum ResponseBody {
     Binary(Vec<u8>),
     /// Empty response
     Empty,
+    /// Streamed response, sent with chunked transfer encoding
+    Stream(BodyStream),
+    /// File on disk, read lazily while sending
+    File(FileBody),
+}
+
+impl ResponseBody {
+    /// Name of the body variant, for logging
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ResponseBody::Text(_) => "text",
+            ResponseBody::Json(_) => "json",
+            ResponseBody::Binary(_) => "binary",
+            ResponseBody::Empty => "empty",
+            ResponseBody::Stream(_) => "stream",
+            ResponseBody::File(_) => "file",
+        }
+    }
+
+    /// Size of the body in bytes, or `None` for streams of unknown length
+    ///
+    /// JSON bodies are measured by serializing them; `Response::get_body_len`
+    /// keeps the serialized bytes so they are only produced once.
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            ResponseBody::Text(text) => Some(text.len() as u64),
+            ResponseBody::Json(json) => serde_json::to_vec(json).ok().map(|data| data.len() as u64),
+            ResponseBody::Binary(data) => Some(data.len() as u64),
+            ResponseBody::Empty => Some(0),
+            ResponseBody::Stream(_) => None,
+            ResponseBody::File(file) => Some(file.len()),
+        }
+    }
+
+    /// Check if the body is known to be empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseBody::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            ResponseBody::Json(json) => f.debug_tuple("Json").field(json).finish(),
+            ResponseBody::Binary(data) => f.debug_tuple("Binary").field(&data.len()).finish(),
+            ResponseBody::Empty => f.write_str("Empty"),
+            ResponseBody::Stream(_) => f.write_str("Stream(..)"),
+            ResponseBody::File(file) => f.debug_tuple("File").field(file).finish(),
+        }
+    }
+}
+
+/// Error returned by `BodySender` once the client has gone away
+#[derive(Debug, Error)]
+#[error("client disconnected")]
+pub struct Disconnected;
+
+/// Sending half of a streamed response body created by `Response::stream_channel`
+///
+/// `send` waits while the channel is full, so a producer is slowed down to
+/// the pace the client reads at.
+#[derive(Debug, Clone)]
+pub struct BodySender {
+    tx: mpsc::Sender<Result<Bytes, BoxError>>,
+}
+
+impl BodySender {
+    /// Send a chunk, failing with `Disconnected` if the client went away
+    pub async fn send<B: Into<Bytes>>(&self, chunk: B) -> Result<(), Disconnected> {
+        self.tx.send(Ok(chunk.into())).await.map_err(|_| Disconnected)
+    }
+
+    /// Abort the response with an error, closing the connection
+    pub async fn abort<E: Into<BoxError>>(self, err: E) {
+        let _ = self.tx.send(Err(err.into())).await;
+    }
+
+    /// Check if the client has gone away
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
 }
 
 impl Response {
@@ -35,28 +145,104 @@ impl Response {
     pub fn new() -> Self {
         Self {
             status: StatusCode::OK,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
             body: ResponseBody::Empty,
+            json_bytes: OnceLock::new(),
+            sealed_cookies: Vec::new(),
         }
     }
 
     /// Set the HTTP status code
     pub fn status(mut self, status: u16) -> Self {
+        self.set_status(status);
+        self
+    }
+
+    /// Set the HTTP status code in place
+    ///
+    /// Codes outside 100-999 are ignored.
+    pub fn set_status(&mut self, status: u16) {
         if let Ok(status_code) = StatusCode::from_u16(status) {
             self.status = status_code;
         }
+    }
+
+    /// Get the HTTP status code
+    pub fn get_status(&self) -> u16 {
+        self.status.as_u16()
+    }
+
+    /// Get the first value of a response header
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Get all response headers
+    pub fn get_headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Get the response body
+    pub fn get_body(&self) -> &ResponseBody {
+        &self.body
+    }
+
+    /// Size of the body in bytes, or `None` for streams of unknown length
+    ///
+    /// JSON bodies are serialized once and the bytes are reused when the
+    /// response is sent.
+    pub fn get_body_len(&self) -> Option<u64> {
+        match self.json_bytes() {
+            Some(bytes) => Some(bytes.len() as u64),
+            None => self.body.len(),
+        }
+    }
+
+    /// Serialized JSON body, or `None` if the body is not JSON
+    pub(crate) fn json_bytes(&self) -> Option<&Bytes> {
+        let ResponseBody::Json(json) = &self.body else {
+            return None;
+        };
+        // Serializing a `Value` cannot fail: its map keys are always strings
+        Some(self.json_bytes.get_or_init(|| serde_json::to_vec(json).unwrap_or_default().into()))
+    }
+
+    /// Replace the body, dropping any cached serialization of the old one
+    fn set_body(&mut self, body: ResponseBody) -> ResponseBody {
+        self.json_bytes = OnceLock::new();
+        std::mem::replace(&mut self.body, body)
+    }
+
+    /// Set a header, replacing any existing value
+    pub fn header<K: Into<String>, V: Into<Vec<u8>>>(mut self, key: K, value: V) -> Self {
+        self.set_header(key, value);
         self
     }
 
-    /// Set a header
-    pub fn header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
-        self.headers.insert(key.into(), value.into());
+    /// Set a header in place, replacing any existing value
+    pub fn set_header<K: Into<String>, V: Into<Vec<u8>>>(&mut self, key: K, value: V) {
+        self.headers.insert(key, value);
+    }
+
+    /// Add a header value without replacing existing ones
+    ///
+    /// Use this for headers that may appear several times, such as
+    /// `Set-Cookie` or `Vary`.
+    pub fn append_header<K: Into<String>, V: Into<Vec<u8>>>(mut self, key: K, value: V) -> Self {
+        self.headers.append(key, value);
         self
     }
 
-    /// Set multiple headers
-    pub fn headers(mut self, headers: HashMap<String, String>) -> Self {
-        self.headers.extend(headers);
+    /// Set multiple headers, replacing existing values with the same names
+    pub fn headers<I, K, V>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<Vec<u8>>,
+    {
+        for (key, value) in headers {
+            self.headers.insert(key, value);
+        }
         self
     }
 
@@ -70,11 +256,16 @@ impl Response {
     /// let response = Response::new().text("Hello, World!");
     /// ```
     pub fn text<S: Into<String>>(mut self, text: S) -> Self {
-        self.body = ResponseBody::Text(text.into());
-        self.headers.insert("content-type".to_string(), "text/plain; charset=utf-8".to_string());
+        self.set_text(text);
         self
     }
 
+    /// Set the response body as plain text in place
+    pub fn set_text<S: Into<String>>(&mut self, text: S) {
+        self.set_body(ResponseBody::Text(text.into()));
+        self.headers.insert("content-type", "text/plain; charset=utf-8");
+    }
+
     /// Set the response body as JSON
     /// 
     /// # Examples
@@ -86,39 +277,291 @@ impl Response {
     /// let response = Response::new().json(json!({"message": "Hello, World!"}));
     /// ```
     pub fn json<T: Serialize>(mut self, data: T) -> Self {
+        self.set_json(data);
+        self
+    }
+
+    /// Set the response body as JSON in place
+    ///
+    /// Answers 500 Internal Server Error if `data` cannot be serialized.
+    pub fn set_json<T: Serialize>(&mut self, data: T) {
         match serde_json::to_value(data) {
             Ok(value) => {
-                self.body = ResponseBody::Json(value);
-                self.headers.insert("content-type".to_string(), "application/json".to_string());
+                self.set_body(ResponseBody::Json(value));
+                self.headers.insert("content-type", "application/json");
             }
             Err(_) => {
                 // Fallback to error response
                 self.status = StatusCode::INTERNAL_SERVER_ERROR;
-                self.body = ResponseBody::Text("Failed to serialize JSON".to_string());
-                self.headers.insert("content-type".to_string(), "text/plain; charset=utf-8".to_string());
+                self.set_body(ResponseBody::Text("Failed to serialize JSON".to_string()));
+                self.headers.insert("content-type", "text/plain; charset=utf-8");
             }
         }
-        self
     }
 
     /// Set the response body as HTML
     pub fn html<S: Into<String>>(mut self, html: S) -> Self {
-        self.body = ResponseBody::Text(html.into());
-        self.headers.insert("content-type".to_string(), "text/html; charset=utf-8".to_string());
+        self.set_html(html);
         self
     }
 
+    /// Set the response body as HTML in place
+    pub fn set_html<S: Into<String>>(&mut self, html: S) {
+        self.set_body(ResponseBody::Text(html.into()));
+        self.headers.insert("content-type", "text/html; charset=utf-8");
+    }
+
     /// Set the response body as binary data
     pub fn binary(mut self, data: Vec<u8>) -> Self {
-        self.body = ResponseBody::Binary(data);
-        self.headers.insert("content-type".to_string(), "application/octet-stream".to_string());
+        self.set_binary(data);
         self
     }
 
+    /// Set the response body as binary data in place
+    pub fn set_binary(&mut self, data: Vec<u8>) {
+        self.set_body(ResponseBody::Binary(data));
+        self.headers.insert("content-type", "application/octet-stream");
+    }
+
+    /// Set the response body as a stream of chunks
+    ///
+    /// The body is sent with chunked transfer encoding as the stream yields.
+    /// Chunks are only polled when the connection can accept more data, and
+    /// the stream is dropped if the client disconnects mid-stream.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use dia_core::Response;
+    /// use futures::{stream, StreamExt};
+    ///
+    /// let rows = stream::iter(vec!["id,name\n", "1,Alice\n", "2,Bob\n"])
+    ///     .map(|row| Ok::<_, std::io::Error>(row.into()));
+    /// let response = Response::new()
+    ///     .header("content-type", "text/csv")
+    ///     .stream(rows);
+    /// ```
+    pub fn stream<S, E>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        self.set_body(ResponseBody::Stream(Box::pin(stream.map(|chunk| chunk.map_err(Into::into)))));
+        if !self.headers.contains_key("content-type") {
+            self.headers.insert("content-type", "application/octet-stream");
+        }
+        self
+    }
+
+    /// Stream the response body from a channel
+    ///
+    /// Returns the response together with a `BodySender` that can be moved
+    /// into a spawned task. `buffer` is the number of chunks that may be
+    /// queued before `send` waits for the client.
+    pub fn stream_channel(self, buffer: usize) -> (Self, BodySender) {
+        let (tx, rx) = mpsc::channel(buffer.max(1));
+        let stream = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        });
+        (self.stream(stream), BodySender { tx })
+    }
+
     /// Create a redirect response
     pub fn redirect<S: Into<String>>(mut self, url: S) -> Self {
         self.status = StatusCode::FOUND;
-        self.headers.insert("location".to_string(), url.into());
+        self.headers.insert("location", url.into());
+        self
+    }
+
+    /// Add a `Set-Cookie` header for the given cookie
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use dia_core::{Cookie, Response, SameSite};
+    ///
+    /// let cookie = Cookie::build("session", "abc123")
+    ///     .path("/")
+    ///     .http_only(true)
+    ///     .secure(true)
+    ///     .same_site(SameSite::Lax)
+    ///     .finish();
+    /// let response = Response::new().cookie(cookie);
+    /// ```
+    pub fn cookie(mut self, cookie: Cookie<'static>) -> Self {
+        self.add_cookie(cookie);
+        self
+    }
+
+    /// Add a cookie in place
+    pub fn add_cookie(&mut self, cookie: Cookie<'static>) {
+        self.headers.append("set-cookie", cookie.encoded().to_string());
+    }
+
+    /// Tell the client to delete a cookie
+    ///
+    /// Browsers only delete a cookie when the path and domain match the ones
+    /// it was set with, so they are kept; a cookie without a path is removed
+    /// from `/`.
+    pub fn remove_cookie(self, mut cookie: Cookie<'static>) -> Self {
+        if cookie.path().is_none() {
+            cookie.set_path("/");
+        }
+        cookie.make_removal();
+        self.cookie(cookie)
+    }
+
+    /// Add a cookie signed with the application cookie key
+    ///
+    /// The signature is applied when the response is sent; the cookie is
+    /// dropped with an error log if no cookie secret is configured.
+    pub fn signed_cookie(mut self, cookie: Cookie<'static>) -> Self {
+        self.sealed_cookies.push((cookie, CookieSeal::Signed));
+        self
+    }
+
+    /// Add a cookie encrypted with the application cookie key
+    pub fn private_cookie(mut self, cookie: Cookie<'static>) -> Self {
+        self.sealed_cookies.push((cookie, CookieSeal::Private));
+        self
+    }
+
+    /// Sign or encrypt pending cookies into `Set-Cookie` headers
+    pub(crate) fn seal_cookies(mut self, key: Option<&CookieKey>) -> Self {
+        if self.sealed_cookies.is_empty() {
+            return self;
+        }
+        let Some(key) = key else {
+            log::error!("Dropping signed/private cookies: no cookie secret configured on Application");
+            self.sealed_cookies.clear();
+            return self;
+        };
+
+        let mut jar = CookieJar::new();
+        for (cookie, seal) in std::mem::take(&mut self.sealed_cookies) {
+            match seal {
+                CookieSeal::Signed => jar.signed_mut(key.key()).add(cookie),
+                CookieSeal::Private => jar.private_mut(key.key()).add(cookie),
+            }
+        }
+        for cookie in jar.delta() {
+            self.headers.append("set-cookie", cookie.encoded().to_string());
+        }
+        self
+    }
+
+    /// Send a file from disk
+    ///
+    /// Sets the content type from the file extension (unless one is already
+    /// set) along with `ETag`, `Last-Modified` and `Accept-Ranges` headers.
+    /// Conditional (`If-None-Match`, `If-Modified-Since`) and `Range` requests
+    /// are answered automatically once the handler returns. Missing files
+    /// produce a 404 response, keeping the headers set so far.
+    ///
+    /// The file's metadata is read right away, which blocks; handlers serving
+    /// many files can call this inside `web::block`, as `StaticFiles` does.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use dia_core::Response;
+    ///
+    /// let resp = Response::new().file("./public/report.pdf");
+    /// ```
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        let path = path.as_ref();
+        match files::open(path) {
+            Ok(opened) => {
+                if !self.headers.contains_key("content-type") {
+                    self.headers.insert("Content-Type", opened.content_type);
+                }
+                self.headers.insert("ETag", opened.etag);
+                if let Some(last_modified) = opened.last_modified {
+                    self.headers.insert("Last-Modified", last_modified);
+                }
+                self.headers.insert("Accept-Ranges", "bytes");
+                self.set_body(ResponseBody::File(opened.body));
+                self
+            }
+            Err(err) => {
+                let (status, message) = if err.kind() == std::io::ErrorKind::NotFound {
+                    (StatusCode::NOT_FOUND, "Not Found")
+                } else {
+                    log::error!("Failed to open {}: {}", path.display(), err);
+                    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+                };
+                // The error text is not encoded like the file would have been
+                self.headers.remove("content-encoding");
+                self.status = status;
+                self.set_text(message);
+                self
+            }
+        }
+    }
+
+    /// Replace the body, keeping the headers, and return the previous one
+    pub(crate) fn replace_body(&mut self, body: ResponseBody) -> ResponseBody {
+        self.set_body(body)
+    }
+
+    /// Apply the request's conditional and range headers
+    ///
+    /// Answers `If-None-Match` / `If-Modified-Since` with 304 Not Modified when
+    /// the response's `ETag` / `Last-Modified` validators match, and narrows
+    /// file bodies to a single `Range` (honouring `If-Range`) with 206 Partial
+    /// Content, or 416 when the range lies outside the file. Only successful
+    /// responses to GET and HEAD are affected.
+    pub fn conditional(mut self, req: &Request) -> Self {
+        if !self.status.is_success() || !matches!(req.method(), "GET" | "HEAD") {
+            return self;
+        }
+        let etag = self.headers.get("etag").map(str::to_string);
+        let last_modified = self.headers.get("last-modified").map(str::to_string);
+
+        // If-Modified-Since is ignored when If-None-Match is present
+        let not_modified = match (req.header("if-none-match"), &etag) {
+            (Some(header), Some(etag)) => files::etag_matches(header, etag),
+            (Some(_), None) => false,
+            (None, _) => match (req.header("if-modified-since"), &last_modified) {
+                (Some(header), Some(modified)) => files::not_modified_since(header, modified),
+                _ => false,
+            },
+        };
+        if not_modified {
+            self.status = StatusCode::NOT_MODIFIED;
+            self.headers.remove("content-type");
+            self.set_body(ResponseBody::Empty);
+            return self;
+        }
+
+        let ResponseBody::File(file) = &mut self.body else {
+            return self;
+        };
+        let Some(range) = req.header("range") else {
+            return self;
+        };
+        if let Some(if_range) = req.header("if-range") {
+            if !files::if_range_matches(if_range, etag.as_deref(), last_modified.as_deref()) {
+                return self;
+            }
+        }
+
+        match files::parse_range(range, file.size()) {
+            ByteRange::Partial { start, len } => {
+                let content_range = format!("bytes {}-{}/{}", start, start + len - 1, file.size());
+                file.set_range(start, len);
+                self.status = StatusCode::PARTIAL_CONTENT;
+                self.headers.insert("Content-Range", content_range);
+            }
+            ByteRange::Unsatisfiable => {
+                let content_range = format!("bytes */{}", file.size());
+                self.status = StatusCode::RANGE_NOT_SATISFIABLE;
+                self.headers.remove("content-type");
+                self.headers.insert("Content-Range", content_range);
+                self.set_body(ResponseBody::Empty);
+            }
+            ByteRange::Full => {}
+        }
         self
     }
 
@@ -137,20 +580,40 @@ impl Response {
     }
 
     /// Convert to actix-web HttpResponse
-    pub fn into_http_response(self) -> HttpResponse {
+    pub fn into_http_response(mut self) -> HttpResponse {
+        // Sealed cookies are normally handled by the route adapter
+        self = self.seal_cookies(None);
         let mut builder = HttpResponse::build(self.status);
 
         // Add headers
-        for (key, value) in self.headers {
-            builder.insert_header((key, value));
+        for (key, value) in self.headers.iter() {
+            builder.append_header((key, value.to_vec()));
         }
 
         // Add body
+        if let Some(bytes) = self.json_bytes().cloned() {
+            if !self.headers.contains_key("content-type") {
+                builder.content_type(mime::APPLICATION_JSON);
+            }
+            return builder.body(bytes);
+        }
         match self.body {
             ResponseBody::Text(text) => builder.body(text),
             ResponseBody::Json(json) => builder.json(json),
             ResponseBody::Binary(data) => builder.body(data),
             ResponseBody::Empty => builder.finish(),
+            ResponseBody::Stream(stream) => builder.streaming(stream.map(|chunk| {
+                chunk.map_err(|err| {
+                    log::warn!("Response stream failed: {}", err);
+                    err as Box<dyn std::error::Error>
+                })
+            })),
+            ResponseBody::File(file) => {
+                let len = file.len();
+                builder.body(SizedStream::new(len, file.into_stream().map(|chunk| {
+                    chunk.inspect_err(|err| log::warn!("File stream failed: {}", err))
+                })))
+            }
         }
     }
 }
@@ -187,4 +650,9 @@ impl Response {
     pub fn forbidden<S: Into<String>>(message: S) -> Self {
         Self::new().status(403).text(message)
     }
+
+    /// Create a 429 Too Many Requests response
+    pub fn too_many_requests<S: Into<String>>(message: S) -> Self {
+        Self::new().status(429).text(message)
+    }
 }
\ No newline at end of file
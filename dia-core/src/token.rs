@@ -0,0 +1,373 @@
+//! Token module for dia framework
+//!
+//! Provides the TokenService for issuing access and refresh tokens with the
+//! same key material as AuthMiddleware, and the revocation store used to
+//! rotate refresh tokens.
+
+use crate::jwt::{Claims, JwtConfig, JwtError, REFRESH_TOKEN_TYPE, TOKEN_TYPE_CLAIM};
+use crate::middleware::AuthMiddleware;
+use rand::RngCore;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default access token lifetime (15 minutes)
+pub const DEFAULT_ACCESS_TTL: u64 = 15 * 60;
+
+/// Default refresh token lifetime (30 days)
+pub const DEFAULT_REFRESH_TTL: u64 = 30 * 24 * 60 * 60;
+
+/// Claims set by the service, which custom claims cannot override
+const REGISTERED_CLAIMS: [&str; 8] = ["sub", "iat", "nbf", "exp", "iss", "aud", "jti", TOKEN_TYPE_CLAIM];
+
+/// Storage for revoked refresh token ids
+///
+/// Implement it on a shared store such as Redis when running several
+/// instances; `MemoryRevocationStore` is the default.
+pub trait RevocationStore: Send + Sync {
+    /// Revoke a token id until `expires_at` (seconds since the Unix epoch)
+    ///
+    /// Returns `false` if the id was already revoked. Must be atomic: it is
+    /// what stops a refresh token from being used twice.
+    fn revoke(&self, jti: &str, expires_at: u64) -> Pin<Box<dyn Future<Output = bool> + Send + '_>>;
+
+    /// Check if a token id was revoked
+    fn is_revoked(&self, jti: &str) -> Pin<Box<dyn Future<Output = bool> + Send + '_>>;
+}
+
+/// In-process revocation store, forgetting ids once their token expires
+#[derive(Debug, Default)]
+pub struct MemoryRevocationStore {
+    /// Revoked ids and their expiry
+    revoked: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryRevocationStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn revoke(&self, jti: &str, expires_at: u64) -> Pin<Box<dyn Future<Output = bool> + Send + '_>> {
+        let now = now();
+        let mut revoked = self.revoked.lock().unwrap_or_else(|e| e.into_inner());
+        revoked.retain(|_, expiry| *expiry > now);
+        let newly_revoked = revoked.insert(jti.to_string(), expires_at).is_none();
+        Box::pin(async move { newly_revoked })
+    }
+
+    fn is_revoked(&self, jti: &str) -> Pin<Box<dyn Future<Output = bool> + Send + '_>> {
+        let revoked = self
+            .revoked
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(jti);
+        Box::pin(async move { revoked })
+    }
+}
+
+/// Access and refresh tokens returned by login and refresh endpoints
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TokenPair {
+    /// Short-lived token for the `Authorization` header
+    pub access_token: String,
+    /// Long-lived token exchanged for a new pair with `TokenService::refresh`
+    pub refresh_token: String,
+    /// Always `Bearer`
+    pub token_type: String,
+    /// Access token lifetime in seconds
+    pub expires_in: u64,
+}
+
+/// Issues access and refresh tokens
+///
+/// Give it the same `JwtConfig` as `AuthMiddleware` (or build the middleware
+/// with `auth_middleware`) and share it with handlers through
+/// `Application::data`.
+///
+/// Refresh tokens are single-use: `refresh` revokes the presented token and
+/// returns a new pair carrying the same custom claims.
+///
+/// # Examples
+///
+/// ```rust
+/// use dia_core::{Application, Request, Response, TokenService};
+/// use dia_core::jwt::JwtConfig;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Role {
+///     role: String,
+/// }
+///
+/// let tokens = TokenService::new(JwtConfig::hmac("secret")).access_ttl(600);
+/// let app = Application::new()
+///     .middleware(tokens.auth_middleware().public_path("POST /login")?)
+///     .data(tokens);
+///
+/// async fn login(req: Request, _resp: Response) -> Response {
+///     let tokens = req.data::<TokenService>().unwrap();
+///     match tokens.issue_pair("user-42", &Role { role: "admin".into() }) {
+///         Ok(pair) => Response::ok_json(pair),
+///         Err(_) => Response::internal_error(),
+///     }
+/// }
+/// # Ok::<(), dia_core::path_pattern::PatternError>(())
+/// ```
+#[derive(Clone)]
+pub struct TokenService {
+    /// Signing and verification keys
+    jwt: JwtConfig,
+    /// Access token lifetime in seconds
+    access_ttl: u64,
+    /// Refresh token lifetime in seconds
+    refresh_ttl: u64,
+    /// Revoked refresh token ids
+    store: Arc<dyn RevocationStore>,
+}
+
+impl TokenService {
+    /// Create a token service signing with the given key material
+    pub fn new(jwt: JwtConfig) -> Self {
+        Self {
+            jwt,
+            access_ttl: DEFAULT_ACCESS_TTL,
+            refresh_ttl: DEFAULT_REFRESH_TTL,
+            store: Arc::new(MemoryRevocationStore::new()),
+        }
+    }
+
+    /// Set the access token lifetime in seconds
+    pub fn access_ttl(mut self, seconds: u64) -> Self {
+        self.access_ttl = seconds;
+        self
+    }
+
+    /// Set the refresh token lifetime in seconds
+    pub fn refresh_ttl(mut self, seconds: u64) -> Self {
+        self.refresh_ttl = seconds;
+        self
+    }
+
+    /// Set the store used to revoke refresh tokens
+    pub fn revocation_store<S: RevocationStore + 'static>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Get the key material
+    pub fn jwt(&self) -> &JwtConfig {
+        &self.jwt
+    }
+
+    /// Create an AuthMiddleware verifying the tokens issued by this service
+    pub fn auth_middleware(&self) -> AuthMiddleware {
+        AuthMiddleware::with_jwt(self.jwt.clone())
+    }
+
+    /// Issue an access token for a subject with custom claims
+    ///
+    /// `custom` must serialize to a JSON object (or `()` for none). The
+    /// registered claims (`sub`, `iat`, `nbf`, `exp`, `iss`, `aud`, `jti`) are
+    /// managed by the service and dropped from `custom`.
+    pub fn issue<T: Serialize>(&self, subject: &str, custom: &T) -> Result<String, JwtError> {
+        let claims = self.claims(subject, custom_claims(custom)?, self.access_ttl, None);
+        self.jwt.sign(&claims)
+    }
+
+    /// Issue an access token and a refresh token
+    pub fn issue_pair<T: Serialize>(&self, subject: &str, custom: &T) -> Result<TokenPair, JwtError> {
+        self.pair(subject, custom_claims(custom)?)
+    }
+
+    /// Exchange a refresh token for a new pair, revoking it
+    ///
+    /// Fails with `JwtError::Revoked` if the token was already used or
+    /// revoked.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, JwtError> {
+        let claims = self.jwt.validate_refresh(refresh_token)?;
+        let (jti, expires_at) = token_id(&claims)?;
+        if !self.store.revoke(jti, expires_at).await {
+            return Err(JwtError::Revoked);
+        }
+
+        let subject = claims.subject().ok_or_else(|| JwtError::MissingClaim("sub".to_string()))?;
+        self.pair(subject, custom_claims(claims.value())?)
+    }
+
+    /// Revoke a refresh token, such as on logout
+    pub async fn revoke(&self, refresh_token: &str) -> Result<(), JwtError> {
+        let claims = self.jwt.validate_refresh(refresh_token)?;
+        let (jti, expires_at) = token_id(&claims)?;
+        self.store.revoke(jti, expires_at).await;
+        Ok(())
+    }
+
+    /// Check if a refresh token was revoked or already used
+    pub async fn is_revoked(&self, refresh_token: &str) -> Result<bool, JwtError> {
+        let claims = self.jwt.validate_refresh(refresh_token)?;
+        let (jti, _) = token_id(&claims)?;
+        Ok(self.store.is_revoked(jti).await)
+    }
+
+    /// Sign an access and refresh token with the same custom claims
+    fn pair(&self, subject: &str, custom: Map<String, Value>) -> Result<TokenPair, JwtError> {
+        let access = self.claims(subject, custom.clone(), self.access_ttl, None);
+        let refresh = self.claims(subject, custom, self.refresh_ttl, Some(REFRESH_TOKEN_TYPE));
+        Ok(TokenPair {
+            access_token: self.jwt.sign(&access)?,
+            refresh_token: self.jwt.sign(&refresh)?,
+            token_type: "Bearer".to_string(),
+            expires_in: self.access_ttl,
+        })
+    }
+
+    /// Add the registered claims to custom claims
+    fn claims(
+        &self,
+        subject: &str,
+        mut claims: Map<String, Value>,
+        ttl: u64,
+        token_type: Option<&str>,
+    ) -> Map<String, Value> {
+        let now = now();
+        claims.insert("sub".to_string(), subject.into());
+        claims.insert("iat".to_string(), now.into());
+        claims.insert("exp".to_string(), (now + ttl).into());
+        claims.insert("jti".to_string(), random_id().into());
+        if let Some(issuer) = self.jwt.get_issuer() {
+            claims.insert("iss".to_string(), issuer.into());
+        }
+        match self.jwt.get_audience() {
+            [] => {}
+            [audience] => {
+                claims.insert("aud".to_string(), audience.as_str().into());
+            }
+            audiences => {
+                claims.insert("aud".to_string(), audiences.into());
+            }
+        }
+        if let Some(token_type) = token_type {
+            claims.insert(TOKEN_TYPE_CLAIM.to_string(), token_type.into());
+        }
+        claims
+    }
+}
+
+/// Serialize custom claims into a JSON object without registered claims
+fn custom_claims<T: Serialize>(custom: &T) -> Result<Map<String, Value>, JwtError> {
+    let mut claims = match serde_json::to_value(custom).map_err(|e| JwtError::InvalidClaims(e.to_string()))? {
+        Value::Object(claims) => claims,
+        Value::Null => Map::new(),
+        _ => return Err(JwtError::InvalidClaims("custom claims must be a JSON object".to_string())),
+    };
+    claims.retain(|name, _| !REGISTERED_CLAIMS.contains(&name.as_str()));
+    Ok(claims)
+}
+
+/// Get the `jti` and `exp` of a refresh token
+fn token_id(claims: &Claims) -> Result<(&str, u64), JwtError> {
+    let jti = claims
+        .get("jti")
+        .and_then(Value::as_str)
+        .ok_or_else(|| JwtError::MissingClaim("jti".to_string()))?;
+    let expires_at = claims
+        .expires_at()
+        .ok_or_else(|| JwtError::MissingClaim("exp".to_string()))?;
+    Ok((jti, expires_at))
+}
+
+/// Generate a random 128-bit token id
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Current time in seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::HandlerFn;
+    use crate::middleware::MiddlewareChain;
+    use crate::{Request, Response};
+    use actix_web::test::TestRequest;
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    fn service() -> TokenService {
+        TokenService::new(JwtConfig::hmac("secret").issuer("dia").audience("api"))
+    }
+
+    #[test]
+    fn issued_tokens_pass_auth_middleware() {
+        let tokens = service();
+        let token = tokens.issue("user-42", &json!({ "role": "admin", "sub": "root" })).unwrap();
+
+        let chain = MiddlewareChain::new(vec![Box::new(tokens.auth_middleware())]);
+        let req = TestRequest::get()
+            .uri("/admin")
+            .insert_header(("authorization", format!("Bearer {}", token)))
+            .to_http_request();
+        let handler: HandlerFn = Arc::new(|req, _resp| {
+            Box::pin(async move {
+                let claims = req.claims().unwrap();
+                Response::ok_json(json!({ "sub": claims.subject(), "role": claims.get("role") }))
+            })
+        });
+        let resp = block_on(chain.handle(Request::new(req), handler));
+        assert_eq!(resp.get_status(), 200);
+        assert_eq!(resp.json_bytes().unwrap().as_ref(), br#"{"role":"admin","sub":"user-42"}"#);
+    }
+
+    #[test]
+    fn refresh_tokens_are_not_access_tokens() {
+        let tokens = service();
+        let pair = tokens.issue_pair("user-42", &()).unwrap();
+        assert!(tokens.jwt().validate(&pair.access_token).is_ok());
+        assert_eq!(tokens.jwt().validate(&pair.refresh_token), Err(JwtError::WrongTokenType));
+        assert_eq!(
+            block_on(tokens.refresh(&pair.access_token)).map(|_| ()),
+            Err(JwtError::WrongTokenType)
+        );
+    }
+
+    #[test]
+    fn refresh_rotates_and_rejects_reuse() {
+        let tokens = service();
+        let pair = tokens.issue_pair("user-42", &json!({ "role": "admin" })).unwrap();
+
+        let rotated = block_on(tokens.refresh(&pair.refresh_token)).unwrap();
+        assert_ne!(rotated.refresh_token, pair.refresh_token);
+        let claims = tokens.jwt().validate(&rotated.access_token).unwrap();
+        assert_eq!(claims.subject(), Some("user-42"));
+        assert_eq!(claims.get("role"), Some(&json!("admin")));
+
+        assert!(block_on(tokens.is_revoked(&pair.refresh_token)).unwrap());
+        assert_eq!(block_on(tokens.refresh(&pair.refresh_token)).map(|_| ()), Err(JwtError::Revoked));
+        assert!(block_on(tokens.refresh(&rotated.refresh_token)).is_ok());
+    }
+
+    #[test]
+    fn revoked_refresh_tokens_are_rejected() {
+        let tokens = service();
+        let pair = tokens.issue_pair("user-42", &()).unwrap();
+        assert!(!block_on(tokens.is_revoked(&pair.refresh_token)).unwrap());
+
+        block_on(tokens.revoke(&pair.refresh_token)).unwrap();
+        assert_eq!(block_on(tokens.refresh(&pair.refresh_token)).map(|_| ()), Err(JwtError::Revoked));
+    }
+}
@@ -7,13 +7,13 @@
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, ItemFn, LitStr, punctuated::Punctuated, Expr, Token};
+use syn::{parse_macro_input, ItemFn};
 
 /// Generate a GET route handler
 /// 
 /// # Examples
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use dia_macros::get;
 /// 
 /// #[get("/users")]
@@ -30,7 +30,7 @@ pub fn get(args: TokenStream, input: TokenStream) -> TokenStream {
 /// 
 /// # Examples
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use dia_macros::post;
 /// 
 /// #[post("/users")]
@@ -66,14 +66,14 @@ fn route_macro(method: &str, args: TokenStream, input: TokenStream) -> TokenStre
     let input_fn = parse_macro_input!(input as ItemFn);
 
     // Parse the path argument
-    let path = if args.is_empty() {
+    if args.is_empty() {
         return syn::Error::new_spanned(
             &input_fn,
             format!("Expected path argument for {} route", method.to_lowercase())
         )
         .to_compile_error()
         .into();
-    };
+    }
 
     let path_str = args.to_string().trim_matches('"').to_string();
 
@@ -103,7 +103,7 @@ fn route_macro(method: &str, args: TokenStream, input: TokenStream) -> TokenStre
 /// 
 /// # Examples
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use dia_macros::controller;
 /// 
 /// #[controller("/api")]
@@ -178,7 +178,7 @@ pub fn routes(_args: TokenStream, input: TokenStream) -> TokenStream {
 /// 
 /// # Examples
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use dia_macros::main;
 /// 
 /// #[main]
@@ -2,7 +2,7 @@ const std = @import("std");
 const dia = @import("dia");
 
 // Handler function for the root endpoint
-fn hello_handler() callconv(.C) ?*opaque {
+fn hello_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*opaque {
     var response = dia.Response.new();
     _ = response.text("Hello, Zig + dia! 🎉") catch {
         std.debug.print("❌ Failed to set response text\n");
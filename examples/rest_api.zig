@@ -2,7 +2,7 @@ const std = @import("std");
 const dia = @import("dia");
 
 // Handler for GET /users endpoint
-fn get_users_handler() callconv(.C) ?*opaque {
+fn get_users_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*opaque {
     const users_json = 
         \\{
         \\  "users": [
@@ -24,7 +24,7 @@ fn get_users_handler() callconv(.C) ?*opaque {
 }
 
 // Handler for GET /health endpoint
-fn health_handler() callconv(.C) ?*opaque {
+fn health_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*opaque {
     const health_json = 
         \\{
         \\  "status": "healthy",
@@ -45,7 +45,7 @@ fn health_handler() callconv(.C) ?*opaque {
 }
 
 // Handler for GET / (root) endpoint
-fn root_handler() callconv(.C) ?*opaque {
+fn root_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*opaque {
     const welcome_json = 
         \\{
         \\  "message": "Welcome to dia REST API!",
@@ -3,7 +3,7 @@
 //! This example demonstrates how to create a basic web server
 //! with dia-core directly (without Zig integration).
 
-use dia_core::{Application, Response, BasicController, Route};
+use dia_core::{Application, Response, BasicController};
 use serde_json::json;
 
 #[tokio::main]
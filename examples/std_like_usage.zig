@@ -57,6 +57,8 @@ fn example2() !void {
     _ = try app.host("127.0.0.1");
     _ = try app.port(3001);
     _ = try app.addController(&api_controller);
+    _ = try app.addMiddleware(&cors_mw);
+    _ = try app.addMiddleware(&logger_mw);
 
     try app.run();
 }
@@ -76,7 +78,7 @@ fn example3() !void {
 }
 
 // Handler functions
-fn hello_handler() callconv(.C) ?*anyopaque {
+fn hello_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*anyopaque {
     // Method 1: Using dia convenience functions
     var resp = dia.ok("Hello from dia framework!") catch {
         std.debug.print("Failed to create response\n");
@@ -88,7 +90,7 @@ fn hello_handler() callconv(.C) ?*anyopaque {
     return null;
 }
 
-fn json_handler() callconv(.C) ?*anyopaque {
+fn json_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*anyopaque {
     // Method 2: Using response module directly
     const json_data =
         \\{"message": "Hello JSON!", "framework": "dia"}
@@ -104,7 +106,7 @@ fn json_handler() callconv(.C) ?*anyopaque {
     return null;
 }
 
-fn users_handler() callconv(.C) ?*anyopaque {
+fn users_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*anyopaque {
     // Method 3: Using struct serialization (like std.json)
     const User = struct {
         id: u32,
@@ -131,7 +133,7 @@ fn users_handler() callconv(.C) ?*anyopaque {
     return null;
 }
 
-fn create_user_handler() callconv(.C) ?*anyopaque {
+fn create_user_handler(_: ?*anyopaque, _: ?*anyopaque) callconv(.C) ?*anyopaque {
     // Method 4: Using error responses (like std.errors)
     var resp = dia.errorResponse(501, "Not implemented yet") catch {
         std.debug.print("Failed to create error response\n");
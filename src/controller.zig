@@ -6,21 +6,25 @@
 const std = @import("std");
 const Request = @import("request.zig").Request;
 const Response = @import("response.zig").Response;
+const MiddlewareHandler = @import("middleware.zig").MiddlewareHandler;
 
 // FFI function declarations for controller handling
 extern "C" fn dia_controller_new() ?*anyopaque;
-extern "C" fn dia_controller_get(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn) c_int;
-extern "C" fn dia_controller_post(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn) c_int;
-extern "C" fn dia_controller_put(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn) c_int;
-extern "C" fn dia_controller_delete(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn) c_int;
+extern "C" fn dia_controller_get(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn, user_data: ?*anyopaque) c_int;
+extern "C" fn dia_controller_post(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn, user_data: ?*anyopaque) c_int;
+extern "C" fn dia_controller_put(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn, user_data: ?*anyopaque) c_int;
+extern "C" fn dia_controller_delete(ctrl: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn, user_data: ?*anyopaque) c_int;
 extern "C" fn dia_controller_middleware(ctrl: ?*anyopaque, middleware: MiddlewareFn) c_int;
 extern "C" fn dia_controller_free(ctrl: ?*anyopaque) void;
 
 /// Handler function type
-pub const HandlerFn = *const fn () callconv(.C) ?*anyopaque;
+///
+/// Receives the request handle and the registered user data, and returns a
+/// response handle whose ownership passes to dia (null answers 500).
+pub const HandlerFn = *const fn (req: ?*anyopaque, user_data: ?*anyopaque) callconv(.C) ?*anyopaque;
 
-/// Middleware function type
-pub const MiddlewareFn = *const fn () callconv(.C) c_int;
+/// Middleware function type, run before each of the controller's routes
+pub const MiddlewareFn = MiddlewareHandler;
 
 /// Route context that handlers receive
 pub const RouteContext = struct {
@@ -30,12 +34,20 @@ pub const RouteContext = struct {
 
     const Self = @This();
 
-    pub fn init(allocator: std.mem.Allocator) Self {
-        return Self{
-            .request = Request.new(),
+    /// Create a context from the request handle passed to a handler
+    pub fn init(allocator: std.mem.Allocator, req: ?*anyopaque) !Self {
+        var self = Self{
+            .request = Request.fromHandle(req),
             .response = Response.new(),
             .params = std.StringHashMap([]const u8).init(allocator),
         };
+        errdefer self.params.deinit();
+
+        var index: usize = 0;
+        while (self.request.paramAt(index)) |pair| : (index += 1) {
+            try self.params.put(pair.name, pair.value);
+        }
+        return self;
     }
 
     pub fn deinit(self: *Self) void {
@@ -80,7 +92,7 @@ pub const Controller = struct {
         const allocator = arena.allocator();
 
         const full_path = try std.fmt.allocPrintZ(allocator, "{s}{s}", .{ self.base_path, path });
-        const result = dia_controller_get(self.ptr, full_path.ptr, handler);
+        const result = dia_controller_get(self.ptr, full_path.ptr, handler, null);
 
         if (result != 0) {
             return error.RouteAddFailed;
@@ -95,7 +107,7 @@ pub const Controller = struct {
         const allocator = arena.allocator();
 
         const full_path = try std.fmt.allocPrintZ(allocator, "{s}{s}", .{ self.base_path, path });
-        const result = dia_controller_post(self.ptr, full_path.ptr, handler);
+        const result = dia_controller_post(self.ptr, full_path.ptr, handler, null);
 
         if (result != 0) {
             return error.RouteAddFailed;
@@ -110,7 +122,7 @@ pub const Controller = struct {
         const allocator = arena.allocator();
 
         const full_path = try std.fmt.allocPrintZ(allocator, "{s}{s}", .{ self.base_path, path });
-        const result = dia_controller_put(self.ptr, full_path.ptr, handler);
+        const result = dia_controller_put(self.ptr, full_path.ptr, handler, null);
 
         if (result != 0) {
             return error.RouteAddFailed;
@@ -125,7 +137,7 @@ pub const Controller = struct {
         const allocator = arena.allocator();
 
         const full_path = try std.fmt.allocPrintZ(allocator, "{s}{s}", .{ self.base_path, path });
-        const result = dia_controller_delete(self.ptr, full_path.ptr, handler);
+        const result = dia_controller_delete(self.ptr, full_path.ptr, handler, null);
 
         if (result != 0) {
             return error.RouteAddFailed;
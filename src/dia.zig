@@ -43,9 +43,17 @@ extern "C" fn dia_application_host(app: ?*anyopaque, host: [*:0]const u8) c_int;
 extern "C" fn dia_application_port(app: ?*anyopaque, port: u16) c_int;
 extern "C" fn dia_application_run(app: ?*anyopaque) c_int;
 extern "C" fn dia_application_free(app: ?*anyopaque) void;
-extern "C" fn dia_application_get(app: ?*anyopaque, path: [*:0]const u8, handler: *const fn () callconv(.C) ?*anyopaque) c_int;
-extern "C" fn dia_application_post(app: ?*anyopaque, path: [*:0]const u8, handler: *const fn () callconv(.C) ?*anyopaque) c_int;
+extern "C" fn dia_application_get(app: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn, user_data: ?*anyopaque) c_int;
+extern "C" fn dia_application_post(app: ?*anyopaque, path: [*:0]const u8, handler: HandlerFn, user_data: ?*anyopaque) c_int;
 extern "C" fn dia_application_controller(app: ?*anyopaque, controller: ?*anyopaque) c_int;
+extern "C" fn dia_application_middleware(app: ?*anyopaque, mw: ?*anyopaque) c_int;
+extern "C" fn dia_application_start(app: ?*anyopaque) ?*anyopaque;
+
+// Server FFI functions
+extern "C" fn dia_server_local_addr(server: ?*anyopaque) request.Slice;
+extern "C" fn dia_server_stop(server: ?*anyopaque, graceful: bool, timeout_ms: u64) c_int;
+extern "C" fn dia_server_wait(server: ?*anyopaque) c_int;
+extern "C" fn dia_server_free(server: ?*anyopaque) void;
 
 /// Initialize the dia framework
 /// This must be called before using any other dia functions
@@ -113,7 +121,7 @@ pub const Application = struct {
         const allocator = arena.allocator();
 
         const c_str = try allocator.dupeZ(u8, path);
-        const result = dia_application_get(self.ptr, c_str.ptr, handler);
+        const result = dia_application_get(self.ptr, c_str.ptr, handler, null);
 
         if (result != 0) {
             return error.RouteAddFailed;
@@ -128,7 +136,7 @@ pub const Application = struct {
         const allocator = arena.allocator();
 
         const c_str = try allocator.dupeZ(u8, path);
-        const result = dia_application_post(self.ptr, c_str.ptr, handler);
+        const result = dia_application_post(self.ptr, c_str.ptr, handler, null);
 
         if (result != 0) {
             return error.RouteAddFailed;
@@ -145,16 +153,38 @@ pub const Application = struct {
         return self;
     }
 
+    /// Add middlewares to the application, wrapping every route
+    pub fn addMiddleware(self: *Self, mw: *Middleware) !*Self {
+        const result = dia_application_middleware(self.ptr, mw.ptr);
+        if (result != 0) {
+            return error.MiddlewareAddFailed;
+        }
+        return self;
+    }
+
     /// Run the application server
     pub fn run(self: *Self) !void {
         print("🚀 Starting dia server on {}:{}...\n", .{ self.host_str orelse "127.0.0.1", self.port_num });
 
+        // The application is consumed by the server
         const result = dia_application_run(self.ptr);
+        self.ptr = null;
         if (result != 0) {
             return error.ServerRunFailed;
         }
     }
 
+    /// Start the application server in the background
+    ///
+    /// Returns once the server listens; the application is consumed.
+    pub fn start(self: *Self) !Server {
+        const ptr = dia_application_start(self.ptr);
+        self.ptr = null;
+        return Server{
+            .ptr = ptr orelse return error.ServerStartFailed,
+        };
+    }
+
     /// Free the application (called automatically by deinit)
     pub fn deinit(self: *Self) void {
         if (self.ptr) |ptr| {
@@ -164,6 +194,45 @@ pub const Application = struct {
     }
 };
 
+/// Server running in the background, started with `Application.start`
+pub const Server = struct {
+    ptr: ?*anyopaque,
+
+    const Self = @This();
+
+    /// Get the address the server listens on, such as "127.0.0.1:8080"
+    ///
+    /// With port 0, this holds the port picked by the system.
+    pub fn localAddr(self: *const Self) []const u8 {
+        return dia_server_local_addr(self.ptr).get() orelse "";
+    }
+
+    /// Stop the server
+    ///
+    /// A graceful stop waits up to `timeout_ms` for requests in progress
+    /// before closing the connections.
+    pub fn stop(self: *Self, graceful: bool, timeout_ms: u64) !void {
+        if (dia_server_stop(self.ptr, graceful, timeout_ms) != 0) {
+            return error.ServerStopFailed;
+        }
+    }
+
+    /// Block until the server has stopped
+    pub fn wait(self: *Self) !void {
+        if (dia_server_wait(self.ptr) != 0) {
+            return error.ServerRunFailed;
+        }
+    }
+
+    /// Free the server, stopping it first if it is still running
+    pub fn deinit(self: *Self) void {
+        if (self.ptr) |ptr| {
+            dia_server_free(ptr);
+            self.ptr = null;
+        }
+    }
+};
+
 // Convenience functions and helpers
 
 /// Create a simple text response
@@ -208,7 +277,7 @@ pub fn testConnection() !void {
 }
 
 // Version and compatibility info
-pub const VERSION = "0.1.0";
+pub const VERSION = "0.2.0";
 pub const AUTHOR = "dia team";
 pub const DESCRIPTION = "Cross-platform backend framework for Zig";
 
@@ -15,6 +15,12 @@ extern "C" fn dia_middleware_custom(mw: ?*anyopaque, handler: MiddlewareHandler)
 extern "C" fn dia_middleware_free(mw: ?*anyopaque) void;
 
 /// Middleware handler function type
+///
+/// Receives the request and a fresh response. Returning 0 continues, and
+/// what was set on the response is applied to the final one: headers are
+/// added, a status other than 200 and a body replace the handler's. Any other
+/// value stops the request and sends the response as set. Handlers run on
+/// dia's blocking thread pool, possibly on several threads at once.
 pub const MiddlewareHandler = *const fn (req: ?*anyopaque, resp: ?*anyopaque) callconv(.C) c_int;
 
 /// Middleware context for custom middleware
@@ -128,7 +134,7 @@ pub fn rateLimitMiddleware(requests_per_minute: u32) MiddlewareHandler {
         fn handler(req: ?*anyopaque, resp: ?*anyopaque) callconv(.C) c_int {
             var request = Request{ .ptr = req };
 
-            const client_ip = request.header("X-Forwarded-For") catch "unknown";
+            const client_ip = (request.header("X-Forwarded-For") catch null) orelse request.remoteIp() orelse "unknown";
             const current_time = std.time.timestamp();
 
             // Reset counts every minute
@@ -2,17 +2,44 @@
 //!
 //! This module provides functionality for handling HTTP requests,
 //! including parsing headers, query parameters, and request body.
+//!
+//! Requests are borrowed from dia: a handler receives a request handle that
+//! stays valid until the handler returns, and every slice read from it
+//! points into dia's memory for that same duration.
 
 const std = @import("std");
 
+/// Borrowed bytes returned by dia, with a null pointer for absent values
+pub const Slice = extern struct {
+    ptr: ?[*]const u8,
+    len: usize,
+
+    /// Get the bytes, or null if the value is absent
+    pub fn get(self: Slice) ?[]const u8 {
+        const ptr = self.ptr orelse return null;
+        return ptr[0..self.len];
+    }
+};
+
 // FFI function declarations for request handling
-extern "C" fn dia_request_new() ?*anyopaque;
-extern "C" fn dia_request_get_method(req: ?*anyopaque) [*:0]const u8;
-extern "C" fn dia_request_get_path(req: ?*anyopaque) [*:0]const u8;
-extern "C" fn dia_request_get_header(req: ?*anyopaque, name: [*:0]const u8) [*:0]const u8;
-extern "C" fn dia_request_get_query(req: ?*anyopaque, key: [*:0]const u8) [*:0]const u8;
-extern "C" fn dia_request_get_body(req: ?*anyopaque) [*:0]const u8;
-extern "C" fn dia_request_free(req: ?*anyopaque) void;
+extern "C" fn dia_request_get_method(req: ?*anyopaque) Slice;
+extern "C" fn dia_request_get_path(req: ?*anyopaque) Slice;
+extern "C" fn dia_request_get_query_string(req: ?*anyopaque) Slice;
+extern "C" fn dia_request_get_query(req: ?*anyopaque, key: [*:0]const u8) Slice;
+extern "C" fn dia_request_get_param(req: ?*anyopaque, name: [*:0]const u8) Slice;
+extern "C" fn dia_request_param_count(req: ?*anyopaque) usize;
+extern "C" fn dia_request_param_at(req: ?*anyopaque, index: usize, name: ?*Slice, value: ?*Slice) c_int;
+extern "C" fn dia_request_get_header(req: ?*anyopaque, name: [*:0]const u8) Slice;
+extern "C" fn dia_request_header_count(req: ?*anyopaque) usize;
+extern "C" fn dia_request_header_at(req: ?*anyopaque, index: usize, name: ?*Slice, value: ?*Slice) c_int;
+extern "C" fn dia_request_get_remote_ip(req: ?*anyopaque) Slice;
+extern "C" fn dia_request_get_body(req: ?*anyopaque) Slice;
+
+/// A name and value pair, such as a header or path parameter
+pub const Pair = struct {
+    name: []const u8,
+    value: []const u8,
+};
 
 /// HTTP Request representation
 pub const Request = struct {
@@ -20,35 +47,49 @@ pub const Request = struct {
 
     const Self = @This();
 
-    /// Create a new request (usually done internally by the framework)
-    pub fn new() Self {
+    /// Wrap the request handle passed to a handler
+    pub fn fromHandle(ptr: ?*anyopaque) Self {
         return Self{
-            .ptr = dia_request_new(),
+            .ptr = ptr,
         };
     }
 
     /// Get the HTTP method (GET, POST, etc.)
     pub fn method(self: *const Self) []const u8 {
-        const c_str = dia_request_get_method(self.ptr);
-        return std.mem.span(c_str);
+        return dia_request_get_method(self.ptr).get() orelse "";
     }
 
     /// Get the request path
     pub fn path(self: *const Self) []const u8 {
-        const c_str = dia_request_get_path(self.ptr);
-        return std.mem.span(c_str);
+        return dia_request_get_path(self.ptr).get() orelse "";
+    }
+
+    /// Get the raw query string
+    pub fn queryString(self: *const Self) []const u8 {
+        return dia_request_get_query_string(self.ptr).get() orelse "";
     }
 
     /// Get a header value by name
-    pub fn header(self: *const Self, name: []const u8) ![]const u8 {
+    pub fn header(self: *const Self, name: []const u8) !?[]const u8 {
         var arena = std.heap.ArenaAllocator.init(std.heap.page_allocator);
         defer arena.deinit();
         const allocator = arena.allocator();
 
         const c_name = try allocator.dupeZ(u8, name);
-        const c_value = dia_request_get_header(self.ptr, c_name.ptr);
+        return dia_request_get_header(self.ptr, c_name.ptr).get();
+    }
 
-        return std.mem.span(c_value);
+    /// Get the number of header values
+    pub fn headerCount(self: *const Self) usize {
+        return dia_request_header_count(self.ptr);
+    }
+
+    /// Get the header at an index, or null past the end
+    pub fn headerAt(self: *const Self, index: usize) ?Pair {
+        var name: Slice = undefined;
+        var value: Slice = undefined;
+        if (dia_request_header_at(self.ptr, index, &name, &value) != 0) return null;
+        return Pair{ .name = name.get() orelse "", .value = value.get() orelse "" };
     }
 
     /// Get a query parameter value by key
@@ -58,16 +99,40 @@ pub const Request = struct {
         const allocator = arena.allocator();
 
         const c_key = try allocator.dupeZ(u8, key);
-        const c_value = dia_request_get_query(self.ptr, c_key.ptr);
+        return dia_request_get_query(self.ptr, c_key.ptr).get();
+    }
+
+    /// Get a path parameter by name
+    pub fn param(self: *const Self, name: []const u8) !?[]const u8 {
+        var arena = std.heap.ArenaAllocator.init(std.heap.page_allocator);
+        defer arena.deinit();
+        const allocator = arena.allocator();
+
+        const c_name = try allocator.dupeZ(u8, name);
+        return dia_request_get_param(self.ptr, c_name.ptr).get();
+    }
+
+    /// Get the number of path parameters
+    pub fn paramCount(self: *const Self) usize {
+        return dia_request_param_count(self.ptr);
+    }
+
+    /// Get the path parameter at an index, or null past the end
+    pub fn paramAt(self: *const Self, index: usize) ?Pair {
+        var name: Slice = undefined;
+        var value: Slice = undefined;
+        if (dia_request_param_at(self.ptr, index, &name, &value) != 0) return null;
+        return Pair{ .name = name.get() orelse "", .value = value.get() orelse "" };
+    }
 
-        if (c_value == null) return null;
-        return std.mem.span(c_value);
+    /// Get the client IP address
+    pub fn remoteIp(self: *const Self) ?[]const u8 {
+        return dia_request_get_remote_ip(self.ptr).get();
     }
 
-    /// Get the request body as string
+    /// Get the request body
     pub fn body(self: *const Self) []const u8 {
-        const c_str = dia_request_get_body(self.ptr);
-        return std.mem.span(c_str);
+        return dia_request_get_body(self.ptr).get() orelse "";
     }
 
     /// Parse JSON body into a structure
@@ -76,16 +141,13 @@ pub const Request = struct {
         return try std.json.parseFromSlice(T, allocator, body_str, .{});
     }
 
-    /// Free the request resources
+    /// Release the handle; the request itself is owned by dia
     pub fn deinit(self: *Self) void {
-        if (self.ptr) |ptr| {
-            dia_request_free(ptr);
-            self.ptr = null;
-        }
+        self.ptr = null;
     }
 };
 
 /// Convenience function to create a request from context
 pub fn from_context(ctx: ?*anyopaque) Request {
-    return Request{ .ptr = ctx };
+    return Request.fromHandle(ctx);
 }